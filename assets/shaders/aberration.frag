@@ -0,0 +1,20 @@
+#version 100
+precision mediump float;
+
+varying mediump vec2 uv;
+
+uniform sampler2D Texture;
+uniform vec2 TextureSize;
+// How far apart the channels get at the edges, in pixels
+uniform float Strength;
+
+void main() {
+    vec2 offset = (uv - 0.5) * 2.0 * Strength / TextureSize;
+
+    gl_FragColor = vec4(
+        texture2D(Texture, uv + offset).r,
+        texture2D(Texture, uv).g,
+        texture2D(Texture, uv - offset).b,
+        1.0
+    );
+}
@@ -0,0 +1,39 @@
+#version 100
+precision mediump float;
+
+varying mediump vec2 uv;
+
+uniform sampler2D Texture;
+uniform vec2 TextureSize;
+
+// Only what is brighter than this glows, which is mostly the particles
+const float THRESHOLD = 0.6;
+// Pixels between the samples of the blur
+const float SPREAD = 3.0;
+const float INTENSITY = 0.8;
+
+vec3 bright(vec2 at) {
+    vec3 color = texture2D(Texture, at).rgb;
+    float luma = dot(color, vec3(0.299, 0.587, 0.114));
+
+    return color * smoothstep(THRESHOLD, 1.0, luma);
+}
+
+void main() {
+    vec2 texel = SPREAD / TextureSize;
+    vec3 glow = vec3(0.0);
+    float total = 0.0;
+
+    for (int x = -2; x <= 2; x++) {
+        for (int y = -2; y <= 2; y++) {
+            vec2 offset = vec2(float(x), float(y));
+            float weight = exp(-dot(offset, offset) / 4.0);
+
+            glow += bright(uv + offset * texel) * weight;
+            total += weight;
+        }
+    }
+
+    vec3 color = texture2D(Texture, uv).rgb + glow / total * INTENSITY;
+    gl_FragColor = vec4(color, 1.0);
+}
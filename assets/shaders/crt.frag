@@ -0,0 +1,30 @@
+#version 100
+precision mediump float;
+
+varying mediump vec2 uv;
+
+uniform sampler2D Texture;
+uniform vec2 TextureSize;
+
+const float CURVATURE = 0.06;
+// Pixels from one scanline to the next, and how dark the gaps get
+const float SCANLINE_HEIGHT = 3.0;
+const float SCANLINE_DEPTH = 0.25;
+const float PI = 3.14159265;
+
+void main() {
+    vec2 centered = uv * 2.0 - 1.0;
+    centered += centered * centered.yx * centered.yx * CURVATURE;
+    vec2 at = centered * 0.5 + 0.5;
+
+    if (at.x < 0.0 || at.x > 1.0 || at.y < 0.0 || at.y > 1.0) {
+        gl_FragColor = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    vec3 color = texture2D(Texture, at).rgb;
+    float line = 0.5 - 0.5 * cos(at.y * TextureSize.y * 2.0 * PI / SCANLINE_HEIGHT);
+    color *= 1.0 - SCANLINE_DEPTH * line;
+
+    gl_FragColor = vec4(color, 1.0);
+}
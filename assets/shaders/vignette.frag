@@ -0,0 +1,16 @@
+#version 100
+precision mediump float;
+
+varying mediump vec2 uv;
+
+uniform sampler2D Texture;
+
+// How dark the corners get
+const float STRENGTH = 0.45;
+
+void main() {
+    vec2 centered = uv - 0.5;
+    float fade = 1.0 - STRENGTH * smoothstep(0.1, 0.5, dot(centered, centered));
+
+    gl_FragColor = vec4(texture2D(Texture, uv).rgb * fade, 1.0);
+}
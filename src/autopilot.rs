@@ -0,0 +1,122 @@
+use crate::{
+    input::{ActionFrame, InputSource, InputView, PaddleInput},
+    physics::{Physics, BALL_RADIUS, BOX_PER_LINE, MAX_X, PLAYER_WIDTH},
+    rng::Rng,
+};
+
+/// The step the ball's flight gets simulated with
+pub const PREDICT_STEP: f32 = 1.0 / 60.0;
+/// How far ahead the prediction looks, in steps
+pub const PREDICT_STEPS: u32 = 600;
+/// How close the paddle has to be to the target to stop moving
+pub const STEER_DEADZONE: f32 = 4.0;
+/// How far from the paddle's center the ball gets caught when aiming
+pub const AIM_OFFSET: f32 = PLAYER_WIDTH * 0.3;
+
+/// Plays the paddle by simulating the ball's flight ahead and going to
+/// where it lands. It only notices that the ball changed direction after
+/// `reaction` seconds and misses its mark by up to `error` pixels.
+#[derive(Clone, Debug)]
+pub struct Autopilot {
+    pub reaction: f32,
+    pub error: f32,
+    /// Whether to catch the ball off-center, so that it flies towards
+    /// the remaining bricks
+    pub aim: bool,
+    target: Option<f32>,
+    /// Whether the ball changed direction since the target was picked
+    stale: bool,
+    seen_dir: (f32, f32),
+    delay: f32,
+    rng: Rng,
+}
+
+impl Autopilot {
+    pub fn new(seed: u64, reaction: f32, error: f32) -> Self {
+        Self {
+            reaction,
+            error,
+            aim: true,
+            target: None,
+            stale: true,
+            seen_dir: (0.0, 0.0),
+            delay: 0.0,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Where the ball is going to reach the paddle, if it does so soon enough
+    pub fn predict_landing(phys: &Physics) -> Option<f32> {
+        let mut sim = *phys;
+        let paddle_y = phys.player_rect().y;
+
+        for _ in 0..PREDICT_STEPS {
+            sim.new_frame();
+            if sim.update(PREDICT_STEP) {
+                return Some(sim.ball_pos.x);
+            }
+
+            // Stop before the paddle bounces the ball back
+            if sim.ball_dir.y > 0.0 && sim.ball_pos.y >= paddle_y - BALL_RADIUS {
+                return Some(sim.ball_pos.x);
+            }
+        }
+
+        None
+    }
+
+    /// Where the paddle's center should be to catch a ball landing at `landing`
+    fn aim_at(phys: &Physics, landing: f32) -> f32 {
+        let (sum, count) = (0..BOX_PER_LINE)
+            .flat_map(|bx| phys.boxes.iter().map(move |row| (bx, row[bx])))
+            .filter(|(_, brick)| brick.is_breakable())
+            .fold((0.0, 0), |(sum, count), (bx, _)| {
+                (sum + Physics::box_rect(bx, 0).center().x, count + 1)
+            });
+        if count == 0 {
+            return landing;
+        }
+
+        // The ball leaves the paddle towards the side it hit, so bricks
+        // on the left want it caught with the left end
+        let bricks_x = sum / count as f32;
+        landing + (landing - bricks_x).clamp(-1.0, 1.0) * AIM_OFFSET
+    }
+}
+
+impl InputSource for Autopilot {
+    fn poll(&mut self, view: &InputView) -> ActionFrame {
+        let phys = view.phys;
+        let dir = (phys.ball_dir.x, phys.ball_dir.y);
+        if dir != self.seen_dir {
+            self.seen_dir = dir;
+            self.delay = self.reaction;
+            self.stale = true;
+        }
+
+        // Until it reacts, it keeps going for the old target
+        self.delay -= view.dt;
+        if self.stale && self.delay <= 0.0 {
+            self.stale = false;
+            self.target = Self::predict_landing(phys).map(|landing| {
+                let target = if self.aim { Self::aim_at(phys, landing) } else { landing };
+
+                (target + self.rng.range(-self.error, self.error))
+                    .clamp(PLAYER_WIDTH / 2.0, MAX_X - PLAYER_WIDTH / 2.0)
+            });
+        }
+
+        let Some(target) = self.target else {
+            return ActionFrame::default();
+        };
+        let offset = target - phys.player_rect().center().x;
+
+        ActionFrame {
+            player: PaddleInput {
+                left: offset < -STEER_DEADZONE,
+                right: offset > STEER_DEADZONE,
+            },
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{
+    autopilot::Autopilot,
+    game_model::player_won,
+    input::{InputSource, InputView},
+    levelgen::{self, MAX_DIFFICULTY},
+    physics::Physics,
+};
+
+/// How many levels get played at every difficulty
+pub const BALANCE_RUNS: u64 = 32;
+pub const BALANCE_STEP: f32 = 1.0 / 60.0;
+/// Runs that take longer than that count as stuck
+pub const BALANCE_TIME_LIMIT: f32 = 600.0;
+/// The skill of the bot, roughly that of an average player
+pub const BOT_REACTION: f32 = 0.2;
+pub const BOT_ERROR: f32 = 16.0;
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Stats {
+    won: u32,
+    stuck: u32,
+    time: f32,
+    score: u32,
+}
+
+/// Lets the bot play generated levels of every difficulty without a
+/// window and prints how it went
+pub fn run() {
+    println!("difficulty  won  stuck  avg time  avg score");
+
+    for difficulty in 0..=MAX_DIFFICULTY {
+        let mut stats = Stats::default();
+
+        for seed in 0..BALANCE_RUNS {
+            let level = levelgen::generate(seed, difficulty);
+            let mut phys = Physics::from_level(&level, seed);
+            let mut bot = Autopilot::new(seed, BOT_REACTION, BOT_ERROR);
+            let mut time = 0.0;
+
+            loop {
+                phys.new_frame();
+                let frame = bot.poll(&InputView {
+                    phys: &phys,
+                    dt: BALANCE_STEP,
+                    two_players: false,
+                });
+                frame.player.apply(&mut phys, BALANCE_STEP);
+                let hit_floor = phys.update(BALANCE_STEP);
+                time += BALANCE_STEP;
+
+                if player_won(&phys) || phys.warped {
+                    stats.won += 1;
+                    break;
+                }
+                if hit_floor {
+                    break;
+                }
+                if time >= BALANCE_TIME_LIMIT {
+                    stats.stuck += 1;
+                    break;
+                }
+            }
+
+            stats.time += time;
+            stats.score += phys.score;
+        }
+
+        println!(
+            "{difficulty:>10}  {:>3}  {:>5}  {:>8.1}  {:>9}",
+            stats.won,
+            stats.stuck,
+            stats.time / BALANCE_RUNS as f32,
+            stats.score / BALANCE_RUNS as u32,
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use macroquad::prelude::*;
+use nanoserde::{DeJson, SerJson};
+
+use crate::{checksum::Checksum, physics::{MAX_X, MAX_Y}, rng::Rng};
+
+pub const BOSS_WIDTH: f32 = 160.0;
+pub const BOSS_HEIGHT: f32 = 48.0;
+pub const BOSS_Y: f32 = 32.0;
+pub const BOSS_HEALTH: u32 = 24;
+pub const BOSS_SPEED: f32 = 60.0;
+pub const BOSS_HIT_SCORE: u32 = 50;
+pub const BOSS_KILL_SCORE: u32 = 2000;
+pub const MAX_PROJECTILES: usize = 8;
+pub const PROJECTILE_RADIUS: f32 = 4.0;
+pub const PROJECTILE_SPEED: f32 = 120.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeJson, SerJson)]
+pub enum Attack {
+    /// Drops shrinking shots straight down while sweeping
+    Sweep,
+    /// Fires stunning shots right at the paddle
+    Aimed,
+    /// Fires a fan of mixed shots and moves faster
+    Barrage,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeJson, SerJson)]
+pub enum ProjectileKind {
+    Shrink,
+    Stun,
+}
+
+#[derive(Clone, Copy, Debug, DeJson, SerJson)]
+pub struct Projectile {
+    #[nserde(proxy = "crate::save::SavedVec2")]
+    pub pos: Vec2,
+    #[nserde(proxy = "crate::save::SavedVec2")]
+    pub vel: Vec2,
+    pub kind: ProjectileKind,
+}
+
+#[derive(Clone, Copy, Debug, DeJson, SerJson)]
+pub struct Boss {
+    pub x: f32,
+    pub health: u32,
+    pub projectiles: [Option<Projectile>; MAX_PROJECTILES],
+    dir: f32,
+    fire_timer: f32,
+    rng: Rng,
+}
+
+impl Boss {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            x: MAX_X / 2.0 - BOSS_WIDTH / 2.0,
+            health: BOSS_HEALTH,
+            projectiles: [None; MAX_PROJECTILES],
+            dir: 1.0,
+            fire_timer: 2.0,
+            rng: Rng::new(seed),
+        }
+    }
+
+    pub fn checksum(&self, sum: &mut Checksum) {
+        sum.f32(self.x);
+        sum.u32(self.health);
+        for slot in &self.projectiles {
+            sum.bool(slot.is_some());
+            if let Some(projectile) = slot {
+                sum.vec2(projectile.pos);
+                sum.vec2(projectile.vel);
+                sum.u32(projectile.kind as u32);
+            }
+        }
+        sum.f32(self.dir);
+        sum.f32(self.fire_timer);
+        self.rng.checksum(sum);
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: self.x,
+            y: BOSS_Y,
+            w: BOSS_WIDTH,
+            h: BOSS_HEIGHT,
+        }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.health > 0
+    }
+
+    /// The boss gets angrier as its health goes down
+    pub fn attack(&self) -> Attack {
+        if self.health * 3 > BOSS_HEALTH * 2 {
+            Attack::Sweep
+        } else if self.health * 3 > BOSS_HEALTH {
+            Attack::Aimed
+        } else {
+            Attack::Barrage
+        }
+    }
+
+    /// Takes a hit from the ball. Returns `true` if that was the last one.
+    pub fn hit(&mut self) -> bool {
+        self.health = self.health.saturating_sub(1);
+
+        if self.health == 0 {
+            self.projectiles = [None; MAX_PROJECTILES];
+            return true;
+        }
+
+        false
+    }
+
+    /// Advances the boss and its projectiles. Returns the kind of the
+    /// projectile that hit one of the paddles, if any.
+    pub fn update(&mut self, dt: f32, paddles: &[Rect]) -> Option<ProjectileKind> {
+        let mut paddle_hit = None;
+
+        for slot in self.projectiles.iter_mut() {
+            let Some(proj) = slot else { continue; };
+            proj.pos += proj.vel * dt;
+
+            let hit = paddles.iter().any(|paddle| {
+                let closest = proj.pos.clamp(paddle.point(), paddle.point() + paddle.size());
+                closest.distance(proj.pos) <= PROJECTILE_RADIUS
+            });
+            if hit {
+                paddle_hit = Some(proj.kind);
+                *slot = None;
+            } else if proj.pos.y - PROJECTILE_RADIUS > MAX_Y {
+                *slot = None;
+            }
+        }
+
+        if !self.is_alive() {
+            return paddle_hit;
+        }
+
+        let attack = self.attack();
+        let speed = match attack {
+            Attack::Barrage => BOSS_SPEED * 1.8,
+            _ => BOSS_SPEED,
+        };
+        self.x += self.dir * speed * dt;
+        if self.x < 0.0 || self.x + BOSS_WIDTH > MAX_X {
+            self.dir *= -1.0;
+            self.x = self.x.clamp(0.0, MAX_X - BOSS_WIDTH);
+        }
+
+        self.fire_timer -= dt;
+        if self.fire_timer > 0.0 {
+            return paddle_hit;
+        }
+
+        let muzzle = vec2(self.x + BOSS_WIDTH / 2.0, BOSS_Y + BOSS_HEIGHT);
+        match attack {
+            Attack::Sweep => {
+                self.fire_timer = 1.6;
+                self.fire(muzzle, vec2(0.0, 1.0), ProjectileKind::Shrink);
+            },
+            Attack::Aimed => {
+                self.fire_timer = 1.2;
+                // Aims at the paddle that is closest to the muzzle
+                let dir = paddles.iter()
+                    .map(|x| x.center())
+                    .min_by(|a, b| a.distance_squared(muzzle).total_cmp(&b.distance_squared(muzzle)))
+                    .map_or(vec2(0.0, 1.0), |x| (x - muzzle).normalize_or_zero());
+                self.fire(muzzle, dir, ProjectileKind::Stun);
+            },
+            Attack::Barrage => {
+                self.fire_timer = self.rng.range(0.8, 1.2);
+                for (angle, kind) in [
+                    (-0.4, ProjectileKind::Stun),
+                    (0.0, ProjectileKind::Shrink),
+                    (0.4, ProjectileKind::Stun),
+                ] {
+                    self.fire(muzzle, Vec2::from_angle(angle).rotate(vec2(0.0, 1.0)), kind);
+                }
+            },
+        }
+
+        paddle_hit
+    }
+
+    fn fire(&mut self, pos: Vec2, dir: Vec2, kind: ProjectileKind) {
+        let Some(slot) = self.projectiles.iter_mut().find(|x| x.is_none()) else { return; };
+
+        *slot = Some(Projectile {
+            pos,
+            vel: dir * PROJECTILE_SPEED,
+            kind,
+        });
+    }
+}
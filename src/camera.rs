@@ -0,0 +1,109 @@
+use macroquad::prelude::*;
+
+use crate::{game_model::GameModel, GameState};
+
+/// How much trauma wears off per second. The shake follows trauma squared,
+/// so it dies down quickly towards the end.
+const TRAUMA_DECAY: f32 = 1.6;
+const EXPLOSION_TRAUMA: f32 = 0.45;
+const BALL_LOST_TRAUMA: f32 = 0.7;
+const BOSS_DEFEAT_TRAUMA: f32 = 1.0;
+/// The shake at full trauma, in arena units and degrees
+const MAX_SHAKE_OFFSET: f32 = 6.0;
+const MAX_SHAKE_ANGLE: f32 = 2.0;
+const SHAKE_FREQUENCY: f32 = 25.0;
+
+/// Every this many bricks of a combo the game freezes for a moment
+const HIT_STOP_COMBO: u32 = 5;
+const HIT_STOP_TIME: f32 = 0.07;
+
+/// How far the camera zooms in when a level is cleared, and how long it takes to settle back
+const ZOOM_PUNCH: f32 = 0.06;
+const ZOOM_PUNCH_TIME: f32 = 0.4;
+
+/// What the effects get scaled by when motion is reduced
+const REDUCED_MOTION: f32 = 0.25;
+
+/// Shake, freeze frames and zoom on top of the arena camera
+pub struct CameraFx {
+    trauma: f32,
+    hit_stop: f32,
+    zoom_punch: f32,
+    motion: f32,
+}
+
+impl CameraFx {
+    pub fn new() -> Self {
+        Self {
+            trauma: 0.0,
+            hit_stop: 0.0,
+            zoom_punch: 0.0,
+            motion: 1.0,
+        }
+    }
+
+    /// Reduced motion tones every effect down
+    pub fn set_reduce_motion(&mut self, reduce: bool) {
+        self.motion = if reduce { REDUCED_MOTION } else { 1.0 };
+    }
+
+    /// Starts the effects for what happened in the frame and runs down the ones going on.
+    /// `dt` is the real frame time, freeze frames don't stop the clock of the effects.
+    pub fn update(&mut self, model: &GameModel, dt: f32) {
+        self.trauma = (self.trauma - TRAUMA_DECAY * dt).max(0.0);
+        self.hit_stop = (self.hit_stop - dt).max(0.0);
+        self.zoom_punch = (self.zoom_punch - dt).max(0.0);
+
+        // The menu plays a game of its own in the background, it stays calm
+        if model.prev_state != GameState::Active {
+            return;
+        }
+
+        if model.exploded() {
+            self.add_trauma(EXPLOSION_TRAUMA);
+        }
+        if model.ball_lost() {
+            self.add_trauma(BALL_LOST_TRAUMA);
+        }
+        if model.boss_defeated() {
+            self.add_trauma(BOSS_DEFEAT_TRAUMA);
+        }
+
+        let (old, new) = (model.old_physics.combo, model.physics.combo);
+        if new / HIT_STOP_COMBO > old / HIT_STOP_COMBO {
+            self.hit_stop = HIT_STOP_TIME * self.motion;
+        }
+
+        if model.stage_cleared {
+            self.zoom_punch = ZOOM_PUNCH_TIME;
+        }
+    }
+
+    fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.0);
+    }
+
+    /// Whether the game should stand still this frame
+    pub fn is_frozen(&self) -> bool {
+        self.hit_stop > 0.0
+    }
+
+    /// Moves, turns and zooms the camera by the effects going on
+    pub fn apply(&self, cam: &mut Camera2D) {
+        let t = get_time() as f32 * SHAKE_FREQUENCY;
+        let shake = self.trauma * self.trauma * self.motion;
+        let offset = vec2(wobble(t, 0.0), wobble(t, 1.3)) * MAX_SHAKE_OFFSET * shake;
+
+        cam.target += offset;
+        cam.rotation += wobble(t, 2.9) * MAX_SHAKE_ANGLE * shake;
+
+        // Snaps in and eases back out
+        let punch = self.zoom_punch / ZOOM_PUNCH_TIME;
+        cam.zoom *= 1.0 + ZOOM_PUNCH * punch * punch * self.motion;
+    }
+}
+
+/// Smooth noise from -1 to 1. `seed` keeps the axes from moving in step.
+fn wobble(t: f32, seed: f32) -> f32 {
+    ((t + seed * 10.0).sin() + (t * 2.3 + seed * 7.0).sin()) * 0.5
+}
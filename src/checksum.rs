@@ -0,0 +1,40 @@
+use macroquad::prelude::*;
+
+/// FNV-1a over the simulation state. Floats go in by their bits, so
+/// even the smallest difference between two machines shows up.
+pub struct Checksum(u64);
+
+impl Default for Checksum {
+    fn default() -> Self {
+        Self(0xCBF2_9CE4_8422_2325)
+    }
+}
+
+impl Checksum {
+    pub fn u64(&mut self, x: u64) {
+        for byte in x.to_le_bytes() {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x0100_0000_01B3);
+        }
+    }
+
+    pub fn u32(&mut self, x: u32) {
+        self.u64(x as u64);
+    }
+
+    pub fn bool(&mut self, x: bool) {
+        self.u64(x as u64);
+    }
+
+    pub fn f32(&mut self, x: f32) {
+        self.u32(x.to_bits());
+    }
+
+    pub fn vec2(&mut self, x: Vec2) {
+        self.f32(x.x);
+        self.f32(x.y);
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
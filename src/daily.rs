@@ -0,0 +1,146 @@
+use std::cmp::Reverse;
+
+use nanoserde::{DeJson, SerJson};
+
+use crate::{levelgen, sys};
+
+pub const DAILY_DIFFICULTY: u32 = 5;
+pub const HISTORY_KEY: &str = "daily_history";
+pub const LEADERBOARD_KEY: &str = "daily_leaderboard";
+/// Keeps the daily chain apart from the endless chains
+const DAILY_SALT: u64 = 0xDA11_C4A1_1E96_E000;
+
+/// Days since the unix epoch, in UTC, so that everyone agrees on the date
+pub fn today() -> u64 {
+    (macroquad::miniquad::date::now() / 86400.0) as u64
+}
+
+/// The seed for both the level layout and the simulation
+pub fn daily_seed(day: u64) -> u64 {
+    levelgen::chain_seed(DAILY_SALT, day as u32)
+}
+
+/// Formats the day as `YYYY-MM-DD`
+pub fn format_day(day: u64) -> String {
+    // Howard Hinnant's `civil_from_days`
+    let z = day as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+
+    format!("{y:04}-{m:02}-{d:02}")
+}
+
+#[derive(Clone, Debug, PartialEq, DeJson, SerJson)]
+pub struct DailyResult {
+    pub day: u64,
+    pub score: u32,
+    pub won: bool,
+}
+
+/// Every daily attempt the player has made on this device
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
+pub struct DailyHistory {
+    pub results: Vec<DailyResult>,
+}
+
+impl DailyHistory {
+    pub fn load() -> Self {
+        sys::storage_load(HISTORY_KEY)
+            .and_then(|x| DeJson::deserialize_json(&x).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        sys::storage_store(HISTORY_KEY, &self.serialize_json())
+    }
+
+    pub fn attempted(&self, day: u64) -> bool {
+        self.results.iter().any(|x| x.day == day)
+    }
+
+    /// Writes down the attempt right away, so that closing the game
+    /// mid-run doesn't grant another one
+    pub fn begin(&mut self, day: u64) -> anyhow::Result<()> {
+        self.record(DailyResult { day, score: 0, won: false })
+    }
+
+    pub fn record(&mut self, result: DailyResult) -> anyhow::Result<()> {
+        match self.results.iter_mut().find(|x| x.day == result.day) {
+            Some(old) => *old = result,
+            None => self.results.push(result),
+        }
+
+        self.save()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, DeJson, SerJson)]
+pub struct LeaderboardEntry {
+    pub player: String,
+    pub day: u64,
+    pub score: u32,
+}
+
+/// Where daily scores get submitted to
+pub trait LeaderboardBackend {
+    fn submit(&mut self, entry: LeaderboardEntry) -> anyhow::Result<()>;
+
+    /// The best `count` entries of the day, best first
+    fn top(&self, day: u64, count: usize) -> anyhow::Result<Vec<LeaderboardEntry>>;
+}
+
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
+struct LeaderboardFile {
+    entries: Vec<LeaderboardEntry>,
+}
+
+/// A leaderboard kept in local storage, like the history. Mostly useful
+/// for testing the daily flow without a server.
+pub struct LocalLeaderboard {
+    key: &'static str,
+}
+
+impl LocalLeaderboard {
+    pub fn new(key: &'static str) -> Self {
+        Self { key }
+    }
+
+    fn read(&self) -> anyhow::Result<LeaderboardFile> {
+        match sys::storage_load(self.key) {
+            Some(src) => Ok(DeJson::deserialize_json(&src)?),
+            None => Ok(LeaderboardFile::default()),
+        }
+    }
+}
+
+impl LeaderboardBackend for LocalLeaderboard {
+    fn submit(&mut self, entry: LeaderboardEntry) -> anyhow::Result<()> {
+        let mut file = self.read()?;
+        file.entries.push(entry);
+
+        sys::storage_store(self.key, &file.serialize_json())
+    }
+
+    fn top(&self, day: u64, count: usize) -> anyhow::Result<Vec<LeaderboardEntry>> {
+        let mut entries = self.read()?.entries.into_iter()
+            .filter(|x| x.day == day)
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|x| Reverse(x.score));
+        entries.truncate(count);
+
+        Ok(entries)
+    }
+}
+
+/// The name daily scores are submitted under
+pub fn player_name() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "player".to_owned())
+}
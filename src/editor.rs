@@ -0,0 +1,118 @@
+use macroquad::prelude::*;
+
+use crate::{
+    level::{save_levels, Level, LevelKind, MAX_BALL_SPEED, MIN_BALL_SPEED},
+    physics::{Brick, BOX_HEIGHT, BOX_LINE_COUNT, BOX_PER_LINE, BOX_WIDTH},
+    ui::EditorUiModel,
+};
+
+pub const BALL_SPEED_STEP: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditorAction {
+    None,
+    Playtest,
+    Exit,
+}
+
+/// The in-game level editor. It edits the loaded levels in place, so
+/// play-testing and switching between levels never lose anything.
+/// Nothing reaches the disk until the designer saves.
+pub struct Editor {
+    pub current: usize,
+    pub brush: Brick,
+    pub status: String,
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Self {
+            current: 0,
+            brush: Brick::Normal,
+            status: String::new(),
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        model: &EditorUiModel,
+        cam: &Camera2D,
+        levels: &mut Vec<Level>,
+    ) -> EditorAction {
+        if let Some(brush) = model.brush() {
+            self.brush = brush;
+        }
+
+        if model.new_level() {
+            let file = Self::free_file_name(levels);
+
+            self.current += 1;
+            self.status = format!("Created {file}");
+            levels.insert(self.current, Level::empty(file));
+        }
+
+        let level_count = levels.len() as i32;
+        self.current = (self.current as i32 + model.level_switch())
+            .rem_euclid(level_count) as usize;
+        let level = &mut levels[self.current];
+
+        let cursor = cam.screen_to_world(model.cursor());
+        if let Some((bx, by)) = Self::cell_under(cursor) {
+            if model.paint() {
+                level.bricks[by][bx] = self.brush;
+            } else if model.erase() {
+                level.bricks[by][bx] = Brick::Empty;
+            }
+        }
+
+        level.ball_speed = (level.ball_speed + model.speed_change() * BALL_SPEED_STEP)
+            .clamp(MIN_BALL_SPEED, MAX_BALL_SPEED);
+
+        if model.toggle_kind() {
+            level.kind = match level.kind {
+                LevelKind::Bricks => LevelKind::Boss,
+                LevelKind::Boss => LevelKind::Bricks,
+            };
+        }
+
+        if model.toggle_enemies() {
+            level.enemies = !level.enemies;
+        }
+
+        if model.save() {
+            self.status = match save_levels(levels, &levels[self.current]) {
+                Ok(()) => format!("Saved {}", levels[self.current].file),
+                Err(e) => format!("Save failed: {e}"),
+            };
+        }
+
+        if model.playtest() {
+            EditorAction::Playtest
+        } else if model.exit() {
+            EditorAction::Exit
+        } else {
+            EditorAction::None
+        }
+    }
+
+    fn cell_under(pos: Vec2) -> Option<(usize, usize)> {
+        if pos.x < 0.0 || pos.y < 0.0 {
+            return None;
+        }
+
+        let bx = (pos.x / BOX_WIDTH) as usize;
+        let by = (pos.y / BOX_HEIGHT) as usize;
+        if bx >= BOX_PER_LINE || by >= BOX_LINE_COUNT {
+            return None;
+        }
+
+        Some((bx, by))
+    }
+
+    fn free_file_name(levels: &[Level]) -> String {
+        (1..)
+            .map(|x| format!("{x:02}.lvl"))
+            .find(|file| levels.iter().all(|x| &x.file != file))
+            .unwrap()
+    }
+}
@@ -0,0 +1,220 @@
+use macroquad::prelude::*;
+use nanoserde::{DeJson, SerJson};
+
+use crate::{checksum::Checksum, physics::MAX_X, physics::MAX_Y, rng::Rng};
+
+pub const MAX_ENEMIES: usize = 4;
+pub const ENEMY_RADIUS: f32 = 8.0;
+pub const ENEMY_SPEED: f32 = 50.0;
+pub const ENEMY_STEER: f32 = 2.0;
+pub const ENEMY_SCORE: u32 = 100;
+pub const GATE_COUNT: usize = 2;
+pub const GATE_WIDTH: f32 = 28.0;
+pub const GATE_OPEN_TIME: f32 = 0.6;
+pub const FIRST_SPAWN_DELAY: f32 = 5.0;
+pub const SPAWN_DELAY_MIN: f32 = 4.0;
+pub const SPAWN_DELAY_MAX: f32 = 9.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeJson, SerJson)]
+pub enum Behaviour {
+    /// Slowly sinks while swaying from side to side
+    Drift,
+    /// Picks a random heading and keeps nudging it
+    Wander,
+    /// Goes for the paddle
+    Chase,
+}
+
+#[derive(Clone, Copy, Debug, DeJson, SerJson)]
+pub struct Enemy {
+    /// Unique per spawn, so that a freed slot that got reused can be told apart
+    pub id: u32,
+    #[nserde(proxy = "crate::save::SavedVec2")]
+    pub pos: Vec2,
+    #[nserde(proxy = "crate::save::SavedVec2")]
+    pub vel: Vec2,
+    pub behaviour: Behaviour,
+    pub age: f32,
+    heading: f32,
+}
+
+#[derive(Clone, Copy, Debug, DeJson, SerJson)]
+pub struct Enemies {
+    pub slots: [Option<Enemy>; MAX_ENEMIES],
+    /// How long each gate is going to stay open
+    pub gates: [f32; GATE_COUNT],
+    spawning: bool,
+    spawn_timer: f32,
+    next_id: u32,
+    rng: Rng,
+}
+
+impl Enemies {
+    pub fn new(seed: u64, spawning: bool) -> Self {
+        Self {
+            slots: [None; MAX_ENEMIES],
+            gates: [0.0; GATE_COUNT],
+            spawning,
+            spawn_timer: FIRST_SPAWN_DELAY,
+            next_id: 0,
+            rng: Rng::new(seed),
+        }
+    }
+
+    pub fn checksum(&self, sum: &mut Checksum) {
+        for slot in &self.slots {
+            sum.bool(slot.is_some());
+            if let Some(enemy) = slot {
+                sum.u32(enemy.id);
+                sum.vec2(enemy.pos);
+                sum.vec2(enemy.vel);
+                sum.u32(enemy.behaviour as u32);
+                sum.f32(enemy.age);
+                sum.f32(enemy.heading);
+            }
+        }
+        for gate in self.gates {
+            sum.f32(gate);
+        }
+        sum.bool(self.spawning);
+        sum.f32(self.spawn_timer);
+        sum.u32(self.next_id);
+        self.rng.checksum(sum);
+    }
+
+    pub fn gate_rect(idx: usize) -> Rect {
+        let center = MAX_X * (idx as f32 + 1.0) / (GATE_COUNT as f32 + 1.0);
+
+        Rect {
+            x: center - GATE_WIDTH / 2.0,
+            y: 0.0,
+            w: GATE_WIDTH,
+            h: ENEMY_RADIUS,
+        }
+    }
+
+    /// Advances the enemies. `Chase` enemies steer to the nearest of `targets`
+    /// and `blocked` tells whether an enemy can't stand at a certain position.
+    pub fn update(&mut self, dt: f32, targets: &[Vec2], blocked: impl Fn(Vec2) -> bool) {
+        for gate in self.gates.iter_mut() {
+            *gate = (*gate - dt).max(0.0);
+        }
+
+        self.spawn_timer -= dt;
+        if self.spawning && self.spawn_timer <= 0.0 {
+            self.spawn_timer = self.rng.range(SPAWN_DELAY_MIN, SPAWN_DELAY_MAX);
+            self.spawn();
+        }
+
+        for enemy in self.slots.iter_mut().flatten() {
+            enemy.age += dt;
+
+            let desired = match enemy.behaviour {
+                Behaviour::Drift => vec2((enemy.age * 1.5).sin(), 0.5),
+                Behaviour::Wander => {
+                    enemy.heading += self.rng.range(-3.0, 3.0) * dt;
+                    Vec2::from_angle(enemy.heading)
+                },
+                Behaviour::Chase => targets.iter()
+                    .min_by(|a, b| a.distance_squared(enemy.pos).total_cmp(&b.distance_squared(enemy.pos)))
+                    .map_or(Vec2::ZERO, |x| (*x - enemy.pos).normalize_or_zero()),
+            };
+            // Enemies that are still in the gate always go down
+            let desired = if enemy.pos.y < ENEMY_RADIUS {
+                vec2(0.0, 1.0)
+            } else {
+                desired.normalize_or_zero()
+            };
+
+            enemy.vel += (desired * ENEMY_SPEED - enemy.vel) * (ENEMY_STEER * dt).min(1.0);
+
+            let mut new_pos = enemy.pos + enemy.vel * dt;
+            if blocked(vec2(new_pos.x, enemy.pos.y)) {
+                enemy.vel.x *= -1.0;
+                new_pos.x = enemy.pos.x;
+            }
+            if blocked(new_pos) {
+                enemy.vel.y *= -1.0;
+                new_pos.y = enemy.pos.y;
+            }
+
+            if new_pos.x - ENEMY_RADIUS < 0.0 || new_pos.x + ENEMY_RADIUS > MAX_X {
+                enemy.vel.x *= -1.0;
+                new_pos.x = new_pos.x.clamp(ENEMY_RADIUS, MAX_X - ENEMY_RADIUS);
+            }
+
+            if new_pos.y + ENEMY_RADIUS > MAX_Y {
+                enemy.vel.y *= -1.0;
+                new_pos.y = MAX_Y - ENEMY_RADIUS;
+            }
+
+            // Once out of the gate, an enemy can't go back
+            if enemy.pos.y >= ENEMY_RADIUS && new_pos.y < ENEMY_RADIUS {
+                enemy.vel.y *= -1.0;
+                new_pos.y = ENEMY_RADIUS;
+            }
+
+            enemy.pos = new_pos;
+        }
+    }
+
+    /// Kills the first enemy touching the circle, returning where it was
+    pub fn hit_by_circle(&mut self, pos: Vec2, radius: f32) -> Option<Vec2> {
+        for slot in self.slots.iter_mut() {
+            let Some(enemy) = slot else { continue; };
+
+            if enemy.pos.distance(pos) > radius + ENEMY_RADIUS {
+                continue;
+            }
+
+            let enemy_pos = enemy.pos;
+            *slot = None;
+
+            return Some(enemy_pos);
+        }
+
+        None
+    }
+
+    /// Kills all enemies touching the rect, returning how many died
+    pub fn hit_by_rect(&mut self, rect: Rect) -> u32 {
+        let mut count = 0;
+
+        for slot in self.slots.iter_mut() {
+            let Some(enemy) = slot else { continue; };
+            let closest = enemy.pos.clamp(rect.point(), rect.point() + rect.size());
+
+            if closest.distance(enemy.pos) > ENEMY_RADIUS {
+                continue;
+            }
+
+            *slot = None;
+            count += 1;
+        }
+
+        count
+    }
+
+    fn spawn(&mut self) {
+        let Some(slot) = self.slots.iter_mut().find(|x| x.is_none()) else { return; };
+
+        let gate = self.rng.below(GATE_COUNT as u32) as usize;
+        let behaviour = match self.rng.below(3) {
+            0 => Behaviour::Drift,
+            1 => Behaviour::Wander,
+            _ => Behaviour::Chase,
+        };
+        let gate_rect = Self::gate_rect(gate);
+
+        self.gates[gate] = GATE_OPEN_TIME;
+        *slot = Some(Enemy {
+            id: self.next_id,
+            pos: vec2(gate_rect.center().x, -ENEMY_RADIUS),
+            vel: vec2(0.0, ENEMY_SPEED),
+            behaviour,
+            age: 0.0,
+            heading: self.rng.range(0.0, std::f32::consts::PI),
+        });
+        self.next_id = self.next_id.wrapping_add(1);
+    }
+}
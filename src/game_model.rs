@@ -1,3 +1,5 @@
+use macroquad::prelude::*;
+
 use crate::{physics::*, GameState};
 
 #[derive(Clone, Copy, Debug)]
@@ -7,6 +9,9 @@ pub struct GameModel {
     pub state: GameState,
     pub old_physics: Physics,
     pub physics: Physics,
+    /// Whether the player got through a level this frame. The physics of the
+    /// next level have replaced the old ones by the time the frame is drawn.
+    pub stage_cleared: bool,
 }
 
 impl GameModel {
@@ -17,7 +22,7 @@ impl GameModel {
     pub fn ball_bounced_off_left_wall(&self) -> bool {
         (
             self.old_physics.ball_pos +
-            self.old_physics.ball_dir * BALL_SPEED * self.dt -
+            self.old_physics.ball_dir * self.old_physics.ball_speed * self.dt -
             BALL_RADIUS
         ).x < 0.0
     }
@@ -25,23 +30,84 @@ impl GameModel {
     pub fn ball_bounced_off_right_wall(&self) -> bool {
         (
             self.old_physics.ball_pos +
-            self.old_physics.ball_dir * BALL_SPEED * self.dt +
+            self.old_physics.ball_dir * self.old_physics.ball_speed * self.dt +
             BALL_RADIUS
         ).x > MAX_X
     }
 
+    /// Whether the ball is going through the floor this frame
+    pub fn ball_lost(&self) -> bool {
+        (
+            self.old_physics.ball_pos +
+            self.old_physics.ball_dir * self.old_physics.ball_speed * self.dt +
+            BALL_RADIUS
+        ).y > MAX_Y
+    }
+
+    /// Whether an explosive brick went off
+    pub fn exploded(&self) -> bool {
+        Self::cells().any(|(bx, by)| {
+            self.old_box(bx, by) == Some(Brick::Explosive) &&
+            self.physics.boxes[by][bx] != Brick::Explosive
+        })
+    }
+
     pub fn broken_box(&self) -> Option<(usize, usize)> {
-        for by in 0..BOX_LINE_COUNT {
-            for bx in 0..BOX_PER_LINE {
-                if self.physics.boxes[by][bx] == self.old_physics.boxes[by][bx] {
-                    continue;
-                }
-
-                return Some((bx, by));
-            }
+        self.broken_boxes().next()
+    }
+
+    /// Every brick that got hit this frame, top row first
+    pub fn broken_boxes(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        Self::cells().filter(|&(bx, by)| {
+            self.old_box(bx, by).is_some_and(|old| old != self.physics.boxes[by][bx])
+        })
+    }
+
+    fn cells() -> impl Iterator<Item = (usize, usize)> {
+        (0..BOX_LINE_COUNT).flat_map(|by| (0..BOX_PER_LINE).map(move |bx| (bx, by)))
+    }
+
+    /// What the cell held before the frame. Garbage rows push the bricks down, so
+    /// they get compared with the cell they came from. New rows have nothing to compare.
+    fn old_box(&self, bx: usize, by: usize) -> Option<Brick> {
+        let shift = self.physics.garbage_rows.saturating_sub(self.old_physics.garbage_rows);
+
+        by.checked_sub(shift as usize).map(|y| self.old_physics.boxes[y][bx])
+    }
+
+    /// Positions of the enemies that died this frame
+    pub fn killed_enemies(&self) -> impl Iterator<Item = Vec2> + '_ {
+        self.old_physics.enemies.slots.iter()
+            .zip(self.physics.enemies.slots.iter())
+            .filter_map(|(old, new)| match (old, new) {
+                (Some(old), Some(new)) if old.id == new.id => None,
+                (Some(old), _) => Some(old.pos),
+                _ => None,
+            })
+    }
+
+    pub fn boss_hit(&self) -> bool {
+        match (self.old_physics.boss, self.physics.boss) {
+            (Some(old), Some(new)) => old.health > new.health,
+            _ => false,
+        }
+    }
+
+    pub fn boss_defeated(&self) -> bool {
+        match (self.old_physics.boss, self.physics.boss) {
+            (Some(old), Some(new)) => old.is_alive() && !new.is_alive(),
+            _ => false,
         }
+    }
+
+    /// Whether a boss projectile has just landed on the paddle
+    pub fn paddle_struck(&self) -> bool {
+        self.physics.shrink_timer > self.old_physics.shrink_timer ||
+        self.physics.stun_timer > self.old_physics.stun_timer
+    }
 
-        None
+    pub fn warp_opened(&self) -> bool {
+        self.physics.warp_timer > self.old_physics.warp_timer
     }
 
     pub fn gameover_just_happened(&self) -> bool {
@@ -50,5 +116,56 @@ impl GameModel {
 }
 
 pub fn player_won(phy: &Physics) -> bool {
-    phy.boxes.iter().flat_map(|x| x.iter()).all(|x| !*x)
-}
\ No newline at end of file
+    phy.boxes.iter().flat_map(|x| x.iter()).all(|x| !x.is_breakable()) &&
+    !phy.boss.is_some_and(|x| x.is_alive())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::Level;
+
+    fn model(old_physics: Physics, physics: Physics) -> GameModel {
+        GameModel {
+            dt: 1.0 / 60.0,
+            prev_state: GameState::Active,
+            state: GameState::Active,
+            old_physics,
+            physics,
+            stage_cleared: false,
+        }
+    }
+
+    fn field() -> Physics {
+        let mut level = Level::empty("test.lvl".to_owned());
+        level.bricks[2] = [Brick::Explosive; BOX_PER_LINE];
+        level.bricks[3] = [Brick::Tough; BOX_PER_LINE];
+
+        Physics::from_level(&level, 3)
+    }
+
+    #[test]
+    fn garbage_rows_break_nothing() {
+        let old = field();
+        let mut new = old;
+        assert!(new.push_garbage_row());
+
+        let model = model(old, new);
+        assert_eq!(model.broken_boxes().count(), 0);
+        assert!(!model.exploded());
+    }
+
+    #[test]
+    fn bricks_break_under_garbage_rows() {
+        let old = field();
+        let mut new = old;
+        new.push_garbage_row();
+        new.push_garbage_row();
+        new.boxes[5][4] = Brick::Cracked;
+        new.boxes[4][7] = Brick::Empty;
+
+        let model = model(old, new);
+        assert_eq!(model.broken_boxes().collect::<Vec<_>>(), [(7, 4), (4, 5)]);
+        assert!(model.exploded());
+    }
+}
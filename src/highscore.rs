@@ -0,0 +1,93 @@
+use nanoserde::{DeJson, SerJson};
+
+use crate::{mode::{format_time, GameMode}, sys};
+
+pub const HIGHSCORE_KEY: &str = "high_scores";
+pub const TABLE_LEN: usize = 5;
+
+#[derive(Clone, Debug, PartialEq, DeJson, SerJson)]
+pub struct HighScore {
+    pub player: String,
+    pub score: u32,
+    /// Run time in seconds
+    pub time: f32,
+}
+
+/// The best runs of every mode. The daily challenge keeps its own
+/// leaderboard, as its runs are only comparable within a day. Versus
+/// matches have no score worth keeping.
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
+pub struct HighScores {
+    #[nserde(default)]
+    classic: Vec<HighScore>,
+    #[nserde(default)]
+    endless: Vec<HighScore>,
+    #[nserde(default)]
+    time_attack: Vec<HighScore>,
+    #[nserde(default)]
+    score_attack: Vec<HighScore>,
+    #[nserde(default)]
+    coop: Vec<HighScore>,
+}
+
+impl HighScores {
+    pub fn load() -> Self {
+        sys::storage_load(HIGHSCORE_KEY)
+            .and_then(|x| DeJson::deserialize_json(&x).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        sys::storage_store(HIGHSCORE_KEY, &self.serialize_json())
+    }
+
+    pub fn table(&self, mode: GameMode) -> &[HighScore] {
+        match mode {
+            GameMode::Classic => &self.classic,
+            GameMode::Endless => &self.endless,
+            GameMode::Daily | GameMode::Versus => &[],
+            GameMode::TimeAttack => &self.time_attack,
+            GameMode::ScoreAttack => &self.score_attack,
+            GameMode::Coop => &self.coop,
+        }
+    }
+
+    /// Puts the entry into the mode's table, returning its place if it made it
+    pub fn submit(&mut self, mode: GameMode, entry: HighScore) -> Option<usize> {
+        let table = match mode {
+            GameMode::Classic => &mut self.classic,
+            GameMode::Endless => &mut self.endless,
+            GameMode::Daily | GameMode::Versus => return None,
+            GameMode::TimeAttack => &mut self.time_attack,
+            GameMode::ScoreAttack => &mut self.score_attack,
+            GameMode::Coop => &mut self.coop,
+        };
+
+        // Time Attack is about the clock, everything else is about points
+        let place = table.iter()
+            .position(|x| match mode {
+                GameMode::TimeAttack => entry.time < x.time,
+                _ => entry.score > x.score,
+            })
+            .unwrap_or(table.len());
+        if place >= TABLE_LEN {
+            return None;
+        }
+
+        table.insert(place, entry);
+        table.truncate(TABLE_LEN);
+
+        Some(place)
+    }
+
+    pub fn board_lines(&self, mode: GameMode) -> Vec<String> {
+        let mut lines = vec![format!("{} best", mode.label())];
+
+        lines.extend(self.table(mode).iter().enumerate().map(|(idx, x)| match mode {
+            GameMode::TimeAttack => format!("{}. {} {}", idx + 1, x.player, format_time(x.time)),
+            _ => format!("{}. {} {}", idx + 1, x.player, x.score),
+        }));
+
+        lines
+    }
+}
@@ -0,0 +1,296 @@
+use macroquad::prelude::*;
+use nanoserde::{DeBin, DeJson, SerBin, SerJson};
+
+use crate::{physics::Physics, sys};
+
+/// Default left and right keys of each player in the two player modes.
+/// Otherwise both sets steer the only paddle.
+pub const PLAYER_KEYS: [(KeyCode, KeyCode); 2] = [
+    (KeyCode::A, KeyCode::D),
+    (KeyCode::Left, KeyCode::Right),
+];
+
+/// Which way a paddle is pushed during a frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, DeJson, SerJson, DeBin, SerBin)]
+pub struct PaddleInput {
+    pub left: bool,
+    pub right: bool,
+}
+
+impl PaddleInput {
+    pub fn is_idle(self) -> bool {
+        !self.left && !self.right
+    }
+
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            left: self.left || other.left,
+            right: self.right || other.right,
+        }
+    }
+
+    pub fn apply(self, phys: &mut Physics, dt: f32) {
+        if self.left {
+            phys.move_player(dt, false);
+        }
+
+        if self.right {
+            phys.move_player(dt, true);
+        }
+    }
+
+    pub fn apply_partner(self, phys: &mut Physics, dt: f32) {
+        if self.left {
+            phys.move_partner(dt, false);
+        }
+
+        if self.right {
+            phys.move_partner(dt, true);
+        }
+    }
+}
+
+/// Everything the players asked for during one tick, no matter where it came from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, DeJson, SerJson)]
+pub struct ActionFrame {
+    pub player: PaddleInput,
+    /// The second player's paddle in the two player modes
+    pub partner: PaddleInput,
+    pub confirm: bool,
+    pub pause: bool,
+    pub fullscreen: bool,
+    pub editor: bool,
+    pub assist: bool,
+    pub menu_move: i32,
+    /// Menu clicks can't be replayed, the menu layout depends on the screen
+    #[nserde(skip)]
+    pub menu_clicked: Option<usize>,
+    pub seed_reroll: bool,
+    pub seed_paste: bool,
+    pub seed_copy: bool,
+    /// Older replays don't have the fields below
+    #[nserde(default)]
+    pub mute: bool,
+    /// How far to turn the selected setting down or up
+    #[nserde(default)]
+    pub menu_adjust: i32,
+}
+
+impl ActionFrame {
+    /// Combines the frames of two sources. Anything either of them asks for happens.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            player: self.player.merge(other.player),
+            partner: self.partner.merge(other.partner),
+            confirm: self.confirm || other.confirm,
+            pause: self.pause || other.pause,
+            fullscreen: self.fullscreen || other.fullscreen,
+            editor: self.editor || other.editor,
+            assist: self.assist || other.assist,
+            menu_move: self.menu_move + other.menu_move,
+            menu_clicked: self.menu_clicked.or(other.menu_clicked),
+            seed_reroll: self.seed_reroll || other.seed_reroll,
+            seed_paste: self.seed_paste || other.seed_paste,
+            seed_copy: self.seed_copy || other.seed_copy,
+            mute: self.mute || other.mute,
+            menu_adjust: self.menu_adjust + other.menu_adjust,
+        }
+    }
+}
+
+/// What input sources get to look at
+pub struct InputView<'a> {
+    pub phys: &'a Physics,
+    pub dt: f32,
+    pub two_players: bool,
+}
+
+/// Anything that can play the game: devices, recordings and bots
+pub trait InputSource {
+    fn poll(&mut self, view: &InputView) -> ActionFrame;
+}
+
+/// Which device steers the paddles. The menus listen to all of them regardless,
+/// so that picking a device that isn't there doesn't lock anyone out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, DeJson, SerJson)]
+pub enum ControlMode {
+    /// Every device steers at once
+    #[default]
+    Auto,
+    Keyboard,
+    /// The mouse or the touch screen
+    Touch,
+    Gamepad,
+}
+
+impl ControlMode {
+    /// Merges the frames of all devices, minus the paddle input of the ones that aren't picked
+    pub fn merge(self, keyboard: ActionFrame, touch: ActionFrame, gamepad: ActionFrame) -> ActionFrame {
+        let steering = |frame: ActionFrame, device: ControlMode| {
+            if self == ControlMode::Auto || self == device {
+                frame
+            } else {
+                ActionFrame {
+                    player: PaddleInput::default(),
+                    partner: PaddleInput::default(),
+                    ..frame
+                }
+            }
+        };
+
+        steering(keyboard, ControlMode::Keyboard)
+            .merge(steering(touch, ControlMode::Touch))
+            .merge(steering(gamepad, ControlMode::Gamepad))
+    }
+}
+
+pub struct Keyboard {
+    /// Left and right keys of each player
+    pub keys: [(KeyCode, KeyCode); 2],
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self { keys: PLAYER_KEYS }
+    }
+}
+
+impl InputSource for Keyboard {
+    fn poll(&mut self, view: &InputView) -> ActionFrame {
+        let [(p1_left, p1_right), (p2_left, p2_right)] = self.keys;
+        let p1 = PaddleInput {
+            left: is_key_down(p1_left),
+            right: is_key_down(p1_right),
+        };
+        let p2 = PaddleInput {
+            left: is_key_down(p2_left),
+            right: is_key_down(p2_right),
+        };
+
+        let mut menu_move = 0;
+        if is_key_pressed(KeyCode::W) || is_key_pressed(KeyCode::Up) {
+            menu_move -= 1;
+        }
+        if is_key_pressed(KeyCode::S) || is_key_pressed(KeyCode::Down) {
+            menu_move += 1;
+        }
+        let mut menu_adjust = 0;
+        if is_key_pressed(KeyCode::A) || is_key_pressed(KeyCode::Left) {
+            menu_adjust -= 1;
+        }
+        if is_key_pressed(KeyCode::D) || is_key_pressed(KeyCode::Right) {
+            menu_adjust += 1;
+        }
+
+        ActionFrame {
+            player: if view.two_players { p1 } else { p1.merge(p2) },
+            partner: if view.two_players { p2 } else { PaddleInput::default() },
+            confirm: is_key_pressed(KeyCode::Space),
+            pause: is_key_pressed(KeyCode::Escape),
+            fullscreen: is_key_pressed(KeyCode::F11),
+            editor: is_key_pressed(KeyCode::F2) && !sys::on_mobile(),
+            assist: is_key_pressed(KeyCode::F3),
+            menu_move,
+            menu_clicked: None,
+            seed_reroll: is_key_pressed(KeyCode::R),
+            seed_paste: is_key_pressed(KeyCode::V),
+            seed_copy: is_key_pressed(KeyCode::C),
+            mute: is_key_pressed(KeyCode::M),
+            menu_adjust,
+        }
+    }
+}
+
+/// Where the touch controls are on the screen. The ui fills it in every
+/// frame, as it depends on the screen size.
+#[derive(Default)]
+pub struct TouchZones {
+    pub cam: Camera2D,
+    pub left: Rect,
+    pub right: Rect,
+    pub menu: Vec<Rect>,
+}
+
+/// The mouse and the touch screen
+#[derive(Default)]
+pub struct Touch {
+    pub zones: TouchZones,
+}
+
+impl InputSource for Touch {
+    fn poll(&mut self, _view: &InputView) -> ActionFrame {
+        let cursor = self.zones.cam.screen_to_world(Vec2::from(mouse_position()));
+        let down = is_mouse_button_down(MouseButton::Left);
+        let pressed = is_mouse_button_pressed(MouseButton::Left);
+        // The paddle buttons are only there on mobile
+        let held = |rect: Rect| sys::on_mobile() && down && rect.contains(cursor);
+
+        ActionFrame {
+            player: PaddleInput {
+                left: held(self.zones.left),
+                right: held(self.zones.right),
+            },
+            confirm: pressed,
+            menu_clicked: self.zones.menu.iter()
+                .position(|x| x.contains(cursor))
+                .filter(|_| pressed),
+            ..Default::default()
+        }
+    }
+}
+
+/// The first connected gamepad. The d-pad or the left stick steers
+/// the paddle and picks menu entries. Only browsers tell us about
+/// gamepads, so the native build goes without.
+#[cfg(target_family = "wasm")]
+#[derive(Default)]
+pub struct Gamepad {
+    held: u32,
+}
+
+#[cfg(target_family = "wasm")]
+impl Gamepad {
+    /// Bits of [sys::gamepad_buttons]
+    const LEFT: u32 = 1 << 0;
+    const RIGHT: u32 = 1 << 1;
+    const UP: u32 = 1 << 2;
+    const DOWN: u32 = 1 << 3;
+    const CONFIRM: u32 = 1 << 4;
+    const PAUSE: u32 = 1 << 5;
+}
+
+#[cfg(target_family = "wasm")]
+impl InputSource for Gamepad {
+    fn poll(&mut self, _view: &InputView) -> ActionFrame {
+        let buttons = sys::gamepad_buttons();
+        let pressed = buttons & !self.held;
+        self.held = buttons;
+
+        let mut menu_move = 0;
+        if pressed & Self::UP != 0 {
+            menu_move -= 1;
+        }
+        if pressed & Self::DOWN != 0 {
+            menu_move += 1;
+        }
+        let mut menu_adjust = 0;
+        if pressed & Self::LEFT != 0 {
+            menu_adjust -= 1;
+        }
+        if pressed & Self::RIGHT != 0 {
+            menu_adjust += 1;
+        }
+
+        ActionFrame {
+            player: PaddleInput {
+                left: buttons & Self::LEFT != 0,
+                right: buttons & Self::RIGHT != 0,
+            },
+            confirm: pressed & Self::CONFIRM != 0,
+            pause: pressed & Self::PAUSE != 0,
+            menu_move,
+            menu_adjust,
+            ..Default::default()
+        }
+    }
+}
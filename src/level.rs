@@ -0,0 +1,175 @@
+use std::fmt;
+
+use anyhow::{bail, Context};
+use macroquad::prelude::*;
+
+use crate::physics::{Brick, BALL_SPEED, BOX_LINE_COUNT, BOX_PER_LINE};
+
+pub const LEVEL_DIR: &str = "assets/levels";
+pub const LEVEL_INDEX: &str = "index.txt";
+pub const MIN_BALL_SPEED: f32 = 120.0;
+pub const MAX_BALL_SPEED: f32 = 300.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LevelKind {
+    Bricks,
+    Boss,
+}
+
+/// A level as designers see it. The file format is a couple of
+/// `key = value` lines, followed by `---` and the brick grid.
+#[derive(Clone, Debug)]
+pub struct Level {
+    /// Name of the file inside [LEVEL_DIR]
+    pub file: String,
+    pub name: String,
+    pub kind: LevelKind,
+    pub ball_speed: f32,
+    pub enemies: bool,
+    /// Overrides the theme picked in the settings
+    pub theme: Option<String>,
+    pub bricks: [[Brick; BOX_PER_LINE]; BOX_LINE_COUNT],
+}
+
+impl Level {
+    pub fn empty(file: String) -> Self {
+        Self {
+            name: file.trim_end_matches(".lvl").to_owned(),
+            file,
+            kind: LevelKind::Bricks,
+            ball_speed: BALL_SPEED,
+            enemies: true,
+            theme: None,
+            bricks: [[Brick::Empty; BOX_PER_LINE]; BOX_LINE_COUNT],
+        }
+    }
+
+    pub fn parse(file: &str, src: &str) -> anyhow::Result<Self> {
+        let mut res = Self::empty(file.to_owned());
+        let mut lines = src.lines().enumerate()
+            .map(|(no, line)| (no + 1, line.trim()))
+            .filter(|(_, line)| !line.starts_with('#'));
+
+        for (no, line) in lines.by_ref() {
+            if line == "---" {
+                break;
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            let Some((key, val)) = line.split_once('=') else {
+                bail!("{file}:{no}: expected `key = value`");
+            };
+            let val = val.trim();
+
+            match key.trim() {
+                "name" => res.name = val.to_owned(),
+                "kind" => res.kind = match val {
+                    "bricks" => LevelKind::Bricks,
+                    "boss" => LevelKind::Boss,
+                    _ => bail!("{file}:{no}: unknown level kind {val:?}"),
+                },
+                "ball_speed" => res.ball_speed = val.parse::<f32>()
+                    .with_context(|| format!("{file}:{no}: bad ball speed"))?
+                    .clamp(MIN_BALL_SPEED, MAX_BALL_SPEED),
+                "enemies" => res.enemies = val.parse()
+                    .with_context(|| format!("{file}:{no}: bad enemies flag"))?,
+                "theme" => res.theme = Some(val.to_owned()),
+                key => bail!("{file}:{no}: unknown key {key:?}"),
+            }
+        }
+
+        let rows = lines.filter(|(_, line)| !line.is_empty());
+        for (by, (no, line)) in rows.enumerate() {
+            if by >= BOX_LINE_COUNT {
+                bail!("{file}:{no}: too many brick rows");
+            }
+            if line.chars().count() != BOX_PER_LINE {
+                bail!("{file}:{no}: a row must be {BOX_PER_LINE} bricks wide");
+            }
+
+            for (bx, ch) in line.chars().enumerate() {
+                res.bricks[by][bx] = Brick::from_char(ch)
+                    .with_context(|| format!("{file}:{no}: unknown brick {ch:?}"))?;
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "name = {}", self.name)?;
+        writeln!(f, "kind = {}", match self.kind {
+            LevelKind::Bricks => "bricks",
+            LevelKind::Boss => "boss",
+        })?;
+        writeln!(f, "ball_speed = {}", self.ball_speed)?;
+        writeln!(f, "enemies = {}", self.enemies)?;
+        if let Some(theme) = &self.theme {
+            writeln!(f, "theme = {theme}")?;
+        }
+        writeln!(f, "---")?;
+
+        for row in self.bricks.iter() {
+            let row = row.iter().map(|x| x.to_char()).collect::<String>();
+            writeln!(f, "{row}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Loads all levels in the order the index file lists them
+pub async fn load_levels() -> anyhow::Result<Vec<Level>> {
+    let index = load_string(&format!("{LEVEL_DIR}/{LEVEL_INDEX}")).await?;
+    let mut res = Vec::new();
+
+    for file in index_files(&index) {
+        let src = load_string(&format!("{LEVEL_DIR}/{file}")).await?;
+        res.push(Level::parse(file, &src)?);
+    }
+
+    if res.is_empty() {
+        bail!("The level index is empty");
+    }
+
+    Ok(res)
+}
+
+/// Same as [load_levels], but straight from the disk, for the tools
+/// that run without a window
+pub fn read_levels() -> anyhow::Result<Vec<Level>> {
+    let index = std::fs::read_to_string(format!("{LEVEL_DIR}/{LEVEL_INDEX}"))?;
+    let mut res = Vec::new();
+
+    for file in index_files(&index) {
+        let src = std::fs::read_to_string(format!("{LEVEL_DIR}/{file}"))?;
+        res.push(Level::parse(file, &src)?);
+    }
+
+    if res.is_empty() {
+        bail!("The level index is empty");
+    }
+
+    Ok(res)
+}
+
+fn index_files(index: &str) -> impl Iterator<Item = &str> {
+    index.lines().map(str::trim).filter(|x| !x.is_empty())
+}
+
+/// Writes the level and the level index back to disk. Only works for
+/// native builds, as the web version has no access to the game files.
+pub fn save_levels(levels: &[Level], level: &Level) -> anyhow::Result<()> {
+    let index = levels.iter()
+        .map(|x| format!("{}\n", x.file))
+        .collect::<String>();
+
+    std::fs::write(format!("{LEVEL_DIR}/{}", level.file), level.to_string())?;
+    std::fs::write(format!("{LEVEL_DIR}/{LEVEL_INDEX}"), index)?;
+
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use crate::{
+    level::{Level, LevelKind, MAX_BALL_SPEED, MIN_BALL_SPEED},
+    physics::{Brick, BALL_SPEED, BOX_LINE_COUNT, BOX_PER_LINE},
+    rng::Rng,
+};
+
+pub const MAX_DIFFICULTY: u32 = 10;
+/// Every that many levels the endless chain throws in a boss
+pub const BOSS_EVERY: u32 = 5;
+
+type Grid = [[Brick; BOX_PER_LINE]; BOX_LINE_COUNT];
+
+/// Mixes the chain seed with the level's position in the chain
+pub fn chain_seed(seed: u64, idx: u32) -> u64 {
+    Rng::new(seed ^ (idx as u64).wrapping_mul(0x2545_F491_4F6C_DD1D)).next_u64()
+}
+
+/// Generates the `idx`-th level of the chain started by `seed`
+pub fn generate_chain(seed: u64, idx: u32) -> Level {
+    let difficulty = idx.min(MAX_DIFFICULTY);
+
+    if idx % BOSS_EVERY == BOSS_EVERY - 1 {
+        let mut level = Level::empty(format!("{seed:016X}-{idx}.lvl"));
+        level.name = format!("Boss {}", idx / BOSS_EVERY + 1);
+        level.kind = LevelKind::Boss;
+        level.ball_speed = ball_speed(difficulty);
+        level.enemies = false;
+
+        return level;
+    }
+
+    let mut level = generate(chain_seed(seed, idx), difficulty);
+    level.file = format!("{seed:016X}-{idx}.lvl");
+
+    level
+}
+
+/// Generates a brick level. The layout is symmetric, its shape comes from
+/// value noise and its special bricks are bought from a budget that grows
+/// with `difficulty`. Solid bricks never wall off anything breakable.
+pub fn generate(seed: u64, difficulty: u32) -> Level {
+    let difficulty = difficulty.min(MAX_DIFFICULTY);
+    let mut rng = Rng::new(seed);
+    let mut level = Level::empty(format!("{seed:016X}.lvl"));
+
+    level.name = format!("{seed:016X}");
+    level.ball_speed = ball_speed(difficulty);
+    level.enemies = difficulty >= 2;
+
+    // The top row stays empty, so that the ball has space to bounce around
+    let rows = (4 + difficulty as usize / 3).min(BOX_LINE_COUNT - 1);
+    let density = 0.45 + 0.03 * difficulty as f32;
+    let noise = ValueNoise::new(&mut rng);
+
+    for by in 1..=rows {
+        for bx in 0..=BOX_PER_LINE / 2 {
+            if noise.sample(bx as f32 / 3.0, by as f32 / 2.0) < density {
+                set_mirrored(&mut level.bricks, bx, by, Brick::Normal);
+            }
+        }
+    }
+
+    place_corridors(&mut level.bricks, &mut rng, difficulty, rows);
+    spend_budget(&mut level.bricks, &mut rng, 2 + difficulty * 2);
+    make_solvable(&mut level.bricks);
+    debug_assert!(is_solvable(&level.bricks));
+
+    if !level.bricks.iter().flatten().any(|x| x.is_breakable()) {
+        level.bricks[1] = [Brick::Normal; BOX_PER_LINE];
+    }
+
+    level
+}
+
+/// Whether every breakable brick can be reached by the ball coming from
+/// below without passing through solid bricks
+pub fn is_solvable(bricks: &Grid) -> bool {
+    let reach = reachable(bricks);
+
+    (0..BOX_LINE_COUNT)
+        .flat_map(|by| (0..BOX_PER_LINE).map(move |bx| (bx, by)))
+        .all(|(bx, by)| !bricks[by][bx].is_breakable() || reach[by][bx])
+}
+
+fn ball_speed(difficulty: u32) -> f32 {
+    (BALL_SPEED + 8.0 * difficulty as f32 - 16.0).clamp(MIN_BALL_SPEED, MAX_BALL_SPEED)
+}
+
+fn set_mirrored(bricks: &mut Grid, bx: usize, by: usize, brick: Brick) {
+    bricks[by][bx] = brick;
+    bricks[by][BOX_PER_LINE - 1 - bx] = brick;
+}
+
+/// Puts pairs of solid columns into the layout. The cells between them
+/// are cleared to form a corridor the ball can travel up.
+fn place_corridors(bricks: &mut Grid, rng: &mut Rng, difficulty: u32, rows: usize) {
+    let count = rng.below(difficulty / 4 + 1);
+
+    for _ in 0..count {
+        let wall_x = 1 + rng.below(BOX_PER_LINE as u32 / 2 - 2) as usize;
+        let top = 2 + rng.below(2) as usize;
+
+        for by in top..=rows {
+            set_mirrored(bricks, wall_x, by, Brick::Solid);
+            set_mirrored(bricks, wall_x + 1, by, Brick::Empty);
+        }
+    }
+}
+
+/// Buys tough bricks for one point and explosive ones for two
+fn spend_budget(bricks: &mut Grid, rng: &mut Rng, mut budget: u32) {
+    let mut tries = 64;
+
+    while budget > 0 && tries > 0 {
+        tries -= 1;
+
+        let bx = rng.below(BOX_PER_LINE as u32 / 2 + 1) as usize;
+        let by = rng.below(BOX_LINE_COUNT as u32) as usize;
+        if bricks[by][bx] != Brick::Normal {
+            continue;
+        }
+
+        if budget >= 2 && rng.below(3) == 0 {
+            set_mirrored(bricks, bx, by, Brick::Explosive);
+            budget -= 2;
+        } else {
+            set_mirrored(bricks, bx, by, Brick::Tough);
+            budget -= 1;
+        }
+    }
+}
+
+/// Turns solid bricks that seal off a breakable pocket into normal
+/// ones until the whole layout can be cleared
+fn make_solvable(bricks: &mut Grid) {
+    loop {
+        let reach = reachable(bricks);
+        let unreachable = |bx: usize, by: usize| {
+            bricks[by][bx].is_breakable() && !reach[by][bx]
+        };
+        let culprit = (0..BOX_LINE_COUNT)
+            .flat_map(|by| (0..BOX_PER_LINE).map(move |bx| (bx, by)))
+            .filter(|&(bx, by)| bricks[by][bx] == Brick::Solid)
+            .find(|&(bx, by)| {
+                let touches_reach = by + 1 == BOX_LINE_COUNT ||
+                    neighbours(bx, by).any(|(nx, ny)| reach[ny][nx]);
+                let touches_pocket = neighbours(bx, by).any(|(nx, ny)| unreachable(nx, ny));
+
+                touches_reach && touches_pocket
+            });
+
+        match culprit {
+            Some((bx, by)) => bricks[by][bx] = Brick::Normal,
+            None => break,
+        }
+    }
+}
+
+/// Flood fill through everything that isn't solid, starting from the
+/// open space under the bricks
+fn reachable(bricks: &Grid) -> [[bool; BOX_PER_LINE]; BOX_LINE_COUNT] {
+    let mut reach = [[false; BOX_PER_LINE]; BOX_LINE_COUNT];
+    let mut stack = (0..BOX_PER_LINE)
+        .map(|bx| (bx, BOX_LINE_COUNT - 1))
+        .collect::<Vec<_>>();
+
+    while let Some((bx, by)) = stack.pop() {
+        if reach[by][bx] || bricks[by][bx] == Brick::Solid {
+            continue;
+        }
+
+        reach[by][bx] = true;
+        stack.extend(neighbours(bx, by));
+    }
+
+    reach
+}
+
+fn neighbours(bx: usize, by: usize) -> impl Iterator<Item = (usize, usize)> {
+    [(0, -1), (0, 1), (-1, 0), (1, 0)].into_iter()
+        .map(move |(dx, dy)| (bx as i32 + dx, by as i32 + dy))
+        .filter(|&(x, y)| {
+            x >= 0 && y >= 0 &&
+            x < BOX_PER_LINE as i32 && y < BOX_LINE_COUNT as i32
+        })
+        .map(|(x, y)| (x as usize, y as usize))
+}
+
+/// Smoothly interpolated random values on a coarse lattice
+struct ValueNoise {
+    lattice: [[f32; 8]; 8],
+}
+
+impl ValueNoise {
+    fn new(rng: &mut Rng) -> Self {
+        let mut lattice = [[0.0; 8]; 8];
+        for val in lattice.iter_mut().flatten() {
+            *val = rng.next_f32();
+        }
+
+        Self { lattice }
+    }
+
+    fn sample(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor() as usize % 7, y.floor() as usize % 7);
+        let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+        let (tx, ty) = (smooth(x.fract()), smooth(y.fract()));
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+
+        let top = lerp(self.lattice[y0][x0], self.lattice[y0][x0 + 1], tx);
+        let bottom = lerp(self.lattice[y0 + 1][x0], self.lattice[y0 + 1][x0 + 1], tx);
+
+        lerp(top, bottom, ty)
+    }
+}
@@ -1,18 +1,71 @@
 use game_model::{player_won, GameModel};
 use macroquad::prelude::*;
 use miniquad::window::set_window_size;
+use std::time::Duration;
+use daily::{DailyHistory, DailyResult, LeaderboardBackend, LeaderboardEntry, LocalLeaderboard};
+use autopilot::Autopilot;
+use editor::{Editor, EditorAction};
+use highscore::{HighScore, HighScores};
+use input::{InputSource, InputView, Keyboard, Touch};
+use mode::{format_time, GameMode, Run, Verdict};
+use net::{NetConfig, Session};
 use physics::Physics;
-use render::Render;
+use render::{Render, Viewport};
+use replay::Recorder;
+use save::SaveFile;
+use settings::{Settings, SettingsEntry};
 use sound_director::SoundDirector;
 use sys::*;
 use ui::Ui;
+use versus::Match;
 
 mod physics;
+mod boss;
+mod enemy;
+mod powerup;
+mod level;
+mod editor;
+mod levelgen;
+mod mode;
+mod daily;
+mod highscore;
+mod versus;
+mod net;
+mod input;
+mod autopilot;
+mod replay;
+mod save;
+mod serialized;
+mod settings;
+mod balance;
+mod rng;
+mod checksum;
 mod render;
+mod camera;
+mod post;
+mod theme;
+mod particle_fx;
 mod sys;
 mod ui;
 mod game_model;
 mod sound_director;
+mod sound_bank;
+mod mixer;
+mod sfxr;
+
+/// How well the autopilot plays in the attract mode
+const ATTRACT_REACTION: f32 = 0.1;
+const ATTRACT_ERROR: f32 = 8.0;
+const ATTRACT_DIFFICULTY: u32 = 3;
+
+/// What the start menu offers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MenuEntry {
+    /// Picks up the saved run where it was left
+    Continue,
+    Mode(GameMode),
+    Settings,
+}
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum GameState {
@@ -22,6 +75,8 @@ enum GameState {
     Win,
     Paused,
     PleaseRotate,
+    Editor,
+    Settings,
 }
 
 fn window_conf() -> Conf {
@@ -35,29 +90,110 @@ fn window_conf() -> Conf {
     }
 }
 
-#[macroquad::main(window_conf)]
-async fn main() {
+fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+
+    // Balancing runs have no use for a window
+    if args.iter().any(|x| x == "--balance") {
+        balance::run();
+        return;
+    }
+
+    // Neither do replay checks
+    if let Some(path) = args.iter().position(|x| x == "--replay").and_then(|idx| args.get(idx + 1)) {
+        if let Err(e) = replay::verify(path) {
+            eprintln!("Failed to verify the replay: {e:?}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let net = match NetConfig::from_args(&args) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("Bad arguments: {e:?}");
+            std::process::exit(1);
+        },
+    };
+    if let Some(config) = net.as_ref().filter(|x| x.headless) {
+        if let Err(e) = net::run_headless(config) {
+            eprintln!("Online match failed: {e:?}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    macroquad::Window::from_config(window_conf(), game(net));
+}
+
+async fn game(net: Option<NetConfig>) {
     let hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |info| {
         sys::panic_screen(&format!("Driver panicked:\n{}", info));
         hook(info);
     }));
 
-    if let Err(e) = run().await {
+    if let Err(e) = run(net).await {
         sys::panic_screen(&format!("Driver exitted with error:\n{:?}", e));
     }
 }
 
-async fn run() -> anyhow::Result<()> {
+async fn run(net: Option<NetConfig>) -> anyhow::Result<()> {
     set_default_filter_mode(FilterMode::Nearest);
+    rand::srand(miniquad::date::now() as u64);
 
-    let mut phys = Physics::new();
+    let mut levels = level::load_levels().await?;
+    let mut editor = Editor::new();
+    let mut playtesting = false;
+    let mut menu_selection = 0;
+    let mut endless_seed = rand::rand() as u64;
+    let mut run = Run::new(GameMode::Classic, rand::rand() as u64);
+    let mut high_scores = HighScores::load();
+    let mut daily_history = DailyHistory::load();
+    let mut leaderboard: Box<dyn LeaderboardBackend> = Box::new(
+        LocalLeaderboard::new(daily::LEADERBOARD_KEY)
+    );
+    let mut daily_scored = false;
+    let mut board = Vec::new();
+    let mut phys = Physics::from_level(&levels[0], rand::rand() as u64);
+    let mut duel = Match::new(phys);
+    let mut session = match &net {
+        Some(config) => Some(Session::new(config, rand::rand() as u64)?),
+        None => None,
+    };
+    // Online matches skip the menu
+    if session.is_some() {
+        run = Run::new(GameMode::Versus, 0);
+    }
+    let mut attract = Autopilot::new(rand::rand() as u64, ATTRACT_REACTION, ATTRACT_ERROR);
+    let mut assist = Autopilot::new(rand::rand() as u64, 0.0, 0.0);
+    let mut assist_on = false;
+    let mut recorder = Recorder::new(&run);
+    // Resumed runs miss their beginning, so there is no point in keeping their replay
+    let mut recording = true;
+    match SaveFile::load() {
+        Ok(file) => save::stage(file),
+        Err(e) => warn!("Dropping the saved run: {e}"),
+    }
+    let mut settings = Settings::load();
+    let mut keyboard = Keyboard { keys: settings.bindings.player_keys() };
+    let mut touch = Touch::default();
+    #[cfg(target_family = "wasm")]
+    let mut gamepad = input::Gamepad::default();
     let mut render = Render::new().await?;
-    let mut sounder = SoundDirector::new().await?;
+    let mut rival_render = render.share(Viewport::Right);
+    render.apply_settings(&settings);
+    rival_render.apply_settings(&settings);
+    let mut sounder = SoundDirector::new().await;
+    sounder.apply_settings(&settings);
     let ui = Ui::new().await?;
 
-    let mut state = GameState::Start;
+    let mut state = if session.is_some() { GameState::Active } else { GameState::Start };
     let mut fullscreen = window_conf().fullscreen;
+    if settings.fullscreen != fullscreen {
+        set_fullscreen(settings.fullscreen);
+        fullscreen = settings.fullscreen;
+    }
     let mut paused_state = state;
 
     // Save old size as leaving fullscreen will give window a different size
@@ -65,6 +201,8 @@ async fn run() -> anyhow::Result<()> {
     let old_size = (window_conf().window_width, window_conf().window_height);
 
     build_textures_atlas();
+    // The run gets saved before the window goes away
+    prevent_quit();
 
     done_loading();
 
@@ -76,7 +214,73 @@ async fn run() -> anyhow::Result<()> {
             state = GameState::PleaseRotate;
         }
 
-        let ui_model = ui.update(state);
+        if is_quit_requested() {
+            flush_save();
+            break;
+        }
+
+        let today = daily::today();
+        let continuable = save::staged().filter(|_| state == GameState::Start);
+        let entries = continuable.iter()
+            .map(|_| MenuEntry::Continue)
+            .chain(GameMode::ALL.map(MenuEntry::Mode))
+            .chain([MenuEntry::Settings])
+            .collect::<Vec<_>>();
+        let mut menu = entries.iter().map(|x| match x {
+            MenuEntry::Continue => match &continuable {
+                Some(file) => format!(
+                    "Continue  {} stage {}",
+                    file.run.mode.label(),
+                    file.run.stage + 1,
+                ),
+                None => "Continue".to_owned(),
+            },
+            MenuEntry::Mode(x @ GameMode::Endless) => {
+                format!("{}  seed {endless_seed:08X}", x.label())
+            },
+            MenuEntry::Mode(x @ GameMode::Daily) if daily_history.attempted(today) => format!(
+                "{}  {} (practice)",
+                x.label(),
+                daily::format_day(today),
+            ),
+            MenuEntry::Mode(x @ GameMode::Daily) => {
+                format!("{}  {}", x.label(), daily::format_day(today))
+            },
+            MenuEntry::Mode(x) => x.label().to_owned(),
+            MenuEntry::Settings => "Settings".to_owned(),
+        }).collect::<Vec<_>>();
+        if state == GameState::Settings {
+            menu = SettingsEntry::ALL.map(|x| x.label(&settings)).to_vec();
+        }
+        // The Continue entry goes away once the saved run is over
+        menu_selection = menu_selection.min(menu.len() - 1);
+        touch.zones = ui.touch_zones(menu.len(), menu_selection);
+        let view = InputView {
+            phys: &phys,
+            dt,
+            two_players: run.mode.is_two_player(),
+        };
+        #[cfg(target_family = "wasm")]
+        let pad_frame = gamepad.poll(&view);
+        #[cfg(not(target_family = "wasm"))]
+        let pad_frame = input::ActionFrame::default();
+        let frame = settings.control_mode.merge(keyboard.poll(&view), touch.poll(&view), pad_frame);
+        let ui_model = ui.update(state, &frame);
+
+        if let Some(session) = &mut session {
+            session.poll();
+        }
+
+        sounder.set_focused(has_focus());
+        if ui_model.mute_toggle_requested() {
+            settings.muted = !settings.muted;
+            sounder.apply_settings(&settings);
+            save_settings(&settings);
+        }
+
+        if ui_model.assist_toggle_requested() {
+            assist_on = !assist_on;
+        }
 
         if ui_model.fullscreen_toggle_requested() {
             // NOTE: macroquad does not update window config when it goes fullscreen
@@ -87,6 +291,8 @@ async fn run() -> anyhow::Result<()> {
             }
 
             fullscreen = !fullscreen;
+            settings.fullscreen = fullscreen;
+            save_settings(&settings);
         }
 
         let mut game_model = GameModel {
@@ -95,38 +301,313 @@ async fn run() -> anyhow::Result<()> {
             state,
             old_physics: phys,
             physics: phys,
+            stage_cleared: false,
+        };
+        let mut rival_model = GameModel {
+            old_physics: duel.right,
+            physics: duel.right,
+            ..game_model
         };
 
         phys.new_frame();
         match state {
+            GameState::Start if ui_model.editor_requested() => {
+                state = GameState::Editor;
+            },
+            GameState::Active | GameState::Paused | GameState::Win | GameState::GameOver
+            if playtesting && ui_model.editor_requested() => {
+                playtesting = false;
+                state = GameState::Editor;
+            },
+            GameState::Win | GameState::GameOver if playtesting && ui_model.confirmation_detected() => {
+                playtesting = false;
+                state = GameState::Editor;
+            },
+            GameState::Start if ui_model.confirmation_detected() && entries[
+                ui_model.menu_clicked().unwrap_or(menu_selection)
+            ] == MenuEntry::Continue => {
+                if let Some(file) = continuable {
+                    run = file.run;
+                    phys = file.physics;
+                    daily_scored = file.daily_scored;
+                    recording = false;
+                    game_model.old_physics = phys;
+                    // Gives the player a moment to get ready
+                    state = GameState::Paused;
+                }
+            },
+            GameState::Start if ui_model.confirmation_detected() && entries[
+                ui_model.menu_clicked().unwrap_or(menu_selection)
+            ] == MenuEntry::Settings => {
+                menu_selection = 0;
+                state = GameState::Settings;
+            },
             GameState::Start if ui_model.confirmation_detected() => {
+                menu_selection = ui_model.menu_clicked().unwrap_or(menu_selection);
+                let MenuEntry::Mode(mode) = entries[menu_selection] else { unreachable!() };
+                run = new_run(mode, endless_seed);
+                recorder = Recorder::new(&run);
+                recording = true;
+                save::stage(None);
+                flush_save();
+                phys = run.stage_physics(&levels);
+                duel = Match::new(phys);
+                rival_model.old_physics = duel.right;
+
+                daily_scored = run.mode == GameMode::Daily && !daily_history.attempted(today);
+                if daily_scored {
+                    if let Err(e) = daily_history.begin(today) {
+                        warn!("Failed to save the daily history: {e}");
+                    }
+                }
+                game_model.old_physics = phys;
+                state = GameState::Active;
+            },
+            GameState::Start => {
+                // The attract mode plays a generated level behind the menu
+                let view = InputView {
+                    phys: &phys,
+                    dt,
+                    two_players: false,
+                };
+                attract.poll(&view).player.apply(&mut phys, dt);
+                let hit_floor = phys.update(dt);
+                if hit_floor || player_won(&phys) || phys.warped {
+                    let seed = rand::rand() as u64;
+
+                    phys = Physics::from_level(&levelgen::generate(seed, ATTRACT_DIFFICULTY), seed);
+                    game_model.old_physics = phys;
+                }
+
+                menu_selection = (menu_selection as i32 + ui_model.menu_move())
+                    .rem_euclid(menu.len() as i32) as usize;
+
+                if ui_model.seed_reroll_requested() {
+                    endless_seed = rand::rand() as u64;
+                }
+
+                if ui_model.seed_paste_requested() {
+                    let pasted = miniquad::window::clipboard_get()
+                        .and_then(|x| u64::from_str_radix(x.trim(), 16).ok());
+                    endless_seed = pasted.unwrap_or(endless_seed);
+                }
+
+                if ui_model.seed_copy_requested() {
+                    miniquad::window::clipboard_set(&format!("{endless_seed:08X}"));
+                }
+            },
+            GameState::Win | GameState::GameOver
+            if session.is_some() && (ui_model.confirmation_detected() || ui_model.pause_requested()) => {
+                // Online matches can't be restarted by one side alone
+                session = None;
+                state = GameState::Start;
+            },
+            GameState::Win if ui_model.confirmation_detected() && !run.over => {
+                let score = phys.score;
+
+                run.next_stage(levels.len());
+                phys = run.stage_physics(&levels);
+                if run.stage != 0 {
+                    phys.score = score;
+                }
+                game_model.old_physics = phys;
                 state = GameState::Active;
             },
             GameState::Win | GameState::GameOver if ui_model.confirmation_detected() => {
-                phys = Physics::new();
+                run = new_run(run.mode, endless_seed);
+                recorder = Recorder::new(&run);
+                recording = true;
+                save::stage(None);
+                flush_save();
+                phys = run.stage_physics(&levels);
+                duel = Match::new(phys);
                 game_model.old_physics = phys;
+                rival_model.old_physics = duel.right;
                 state = GameState::Active;
             },
+            GameState::Win | GameState::GameOver if ui_model.pause_requested() => {
+                // A run left between two stages can still be continued
+                flush_save();
+                state = GameState::Start;
+            },
+            GameState::Settings => {
+                menu_selection = (menu_selection as i32 + ui_model.menu_move())
+                    .rem_euclid(menu.len() as i32) as usize;
+                menu_selection = ui_model.menu_clicked().unwrap_or(menu_selection);
+                let entry = SettingsEntry::ALL[menu_selection];
+
+                if ui_model.pause_requested() ||
+                    (ui_model.confirmation_detected() && entry == SettingsEntry::Back) {
+                    // The selection gets clamped to the menu, which ends with the settings
+                    menu_selection = usize::MAX;
+                    state = GameState::Start;
+                } else if ui_model.confirmation_detected() || ui_model.menu_adjust() != 0 {
+                    if entry == SettingsEntry::Theme {
+                        let steps = if ui_model.confirmation_detected() { 1 } else { ui_model.menu_adjust() };
+                        settings.step_theme(&render.theme_ids(), steps);
+                    } else if ui_model.confirmation_detected() {
+                        settings.cycle(entry);
+                    }
+                    settings.adjust(entry, ui_model.menu_adjust());
+                    sounder.apply_settings(&settings);
+                    render.apply_settings(&settings);
+                    rival_render.apply_settings(&settings);
+                    save_settings(&settings);
+                }
+            },
+            GameState::Editor => {
+                let editor_model = ui.update_editor();
+
+                match editor.update(&editor_model, &Render::arena_cam(), &mut levels) {
+                    EditorAction::Playtest => {
+                        playtesting = true;
+                        run = Run::new(GameMode::Classic, rand::rand() as u64);
+                        recorder = Recorder::new(&run);
+                        state = GameState::Active;
+                    },
+                    EditorAction::Exit => {
+                        state = GameState::Start;
+                    },
+                    EditorAction::None => (),
+                }
+
+                phys = Physics::from_level(&levels[editor.current], rand::rand() as u64);
+                game_model.old_physics = phys;
+            },
             GameState::Paused if ui_model.pause_requested() => {
                 state = GameState::Active;
             },
-            GameState::Active => {
-                if ui_model.move_left() {
-                    phys.move_player(dt, false);
+            GameState::Active if run.mode == GameMode::Versus => {
+                let winner = match &mut session {
+                    Some(session) => {
+                        if let Some(seed) = session.pending_seed() {
+                            run = Run::new(GameMode::Versus, seed);
+                            session.start(Match::new(run.stage_physics(&levels)));
+                        }
+
+                        // Online both key sets steer the local paddle
+                        session.advance(frame.player.merge(frame.partner), dt);
+                        duel = session.current().copied().unwrap_or(duel);
+                        session.outcome()
+                    },
+                    // Both sides are steered like the only paddle of their field
+                    None => duel.step([frame.player, frame.partner], dt),
+                };
+                phys = duel.left;
+                run.tick(dt);
+
+                match winner {
+                    Some(side) => {
+                        run.over = true;
+                        board = vec![format!("{} wins", side.label())];
+                        state = GameState::Win;
+                    },
+                    None if session.is_none() && ui_model.pause_requested() => {
+                        state = GameState::Paused;
+                    },
+                    None => (),
                 }
 
-                if ui_model.move_right() {
-                    phys.move_player(dt, true);
+                if let Some(tick) = session.as_ref().and_then(Session::desync) {
+                    run.over = true;
+                    board = vec![format!("Desync at tick {tick}")];
+                    state = GameState::GameOver;
+                }
+            },
+            // Freeze frames hold the field still, only pausing gets through
+            GameState::Active if render.is_frozen() && ui_model.pause_requested() => {
+                state = GameState::Paused;
+            },
+            GameState::Active if render.is_frozen() => (),
+            GameState::Active => {
+                let mut played = frame;
+                // The assist only steers while the player keeps off the controls
+                if assist_on && played.player.is_idle() {
+                    let view = InputView {
+                        phys: &phys,
+                        dt,
+                        two_players: run.mode.is_two_player(),
+                    };
+                    played.player = assist.poll(&view).player;
                 }
+                recorder.record(dt, played);
+                played.player.apply(&mut phys, dt);
+                played.partner.apply_partner(&mut phys, dt);
 
                 let hit_floor = phys.update(dt);
+                run.tick(dt);
 
-                if player_won(&phys) {
-                    state = GameState::Win;
-                } else if hit_floor {
-                    state = GameState::GameOver;
-                } else if ui_model.pause_requested() {
-                    state = GameState::Paused;
+                let cleared = player_won(&phys) || phys.warped;
+                match run.judge(cleared, hit_floor, levels.len()) {
+                    Verdict::Playing if ui_model.pause_requested() => {
+                        state = GameState::Paused;
+                    },
+                    Verdict::Playing => (),
+                    Verdict::Respawn => phys.respawn_ball(),
+                    Verdict::Advance => {
+                        let score = phys.score;
+
+                        run.next_stage(levels.len());
+                        phys = run.stage_physics(&levels);
+                        phys.score = score;
+                        game_model.old_physics = phys;
+                        game_model.stage_cleared = true;
+                    },
+                    Verdict::Cleared | Verdict::Finished => {
+                        game_model.stage_cleared = true;
+                        state = GameState::Win;
+                    },
+                    Verdict::Lost => state = GameState::GameOver,
+                }
+
+                if run.over && run.mode == GameMode::Daily {
+                    if daily_scored {
+                        daily_scored = false;
+                        record_daily(
+                            &mut daily_history,
+                            leaderboard.as_mut(),
+                            DailyResult {
+                                day: today,
+                                score: phys.score,
+                                won: state == GameState::Win,
+                            },
+                        );
+                    }
+                    board = daily_board_lines(&daily_history, leaderboard.as_ref(), today);
+                } else if run.over && !playtesting {
+                    // Only complete runs count against the clock
+                    let counts = run.mode != GameMode::TimeAttack || state == GameState::Win;
+                    let entry = HighScore {
+                        player: daily::player_name(),
+                        score: phys.score,
+                        time: run.time,
+                    };
+
+                    if counts && high_scores.submit(run.mode, entry).is_some() {
+                        if let Err(e) = high_scores.save() {
+                            warn!("Failed to save the high scores: {e}");
+                        }
+                    }
+                    board = high_scores.board_lines(run.mode);
+                }
+
+                if run.over && !playtesting && recording {
+                    if let Err(e) = recorder.save() {
+                        warn!("Failed to save the replay: {e}");
+                    }
+                }
+
+                if !playtesting {
+                    if run.over {
+                        save::stage(None);
+                        flush_save();
+                    } else if matches!(state, GameState::Active | GameState::Paused) {
+                        save::stage(Some(SaveFile::new(&run, phys, daily_scored)));
+                    }
+
+                    if state == GameState::Paused {
+                        flush_save();
+                    }
                 }
             },
             GameState::PleaseRotate if get_orientation() == 0.0 => {
@@ -137,13 +618,182 @@ async fn run() -> anyhow::Result<()> {
 
         game_model.state = state;
         game_model.physics = phys;
+        rival_model.state = state;
+        rival_model.physics = duel.right;
+        let split_screen = run.mode == GameMode::Versus && matches!(
+            state,
+            GameState::Active | GameState::Paused | GameState::Win | GameState::GameOver,
+        );
 
         /*  =================== model is valid past this line ================ */
 
-        render.draw(&game_model);
+        let level_theme = match state {
+            GameState::Editor => levels[editor.current].theme.as_deref(),
+            GameState::Active | GameState::Paused | GameState::Win | GameState::GameOver if playtesting =>
+                levels[editor.current].theme.as_deref(),
+            GameState::Active | GameState::Paused | GameState::Win | GameState::GameOver =>
+                run.stage_level(&levels).and_then(|x| x.theme.as_deref()),
+            _ => None,
+        };
+        render.select_theme(level_theme.unwrap_or(&settings.theme));
+        rival_render.select_theme(level_theme.unwrap_or(&settings.theme));
+
+        if split_screen {
+            render.set_viewport(Viewport::Left);
+            render.draw(&game_model);
+            rival_render.draw(&rival_model);
+        } else {
+            render.set_viewport(Viewport::Full);
+            render.draw(&game_model);
+        }
         ui.draw(ui_model);
-        sounder.direct_sounds(&game_model);
+        match state {
+            GameState::Start | GameState::Settings => ui.draw_menu(&menu, menu_selection),
+            GameState::Editor => ui.draw_editor(&editor, &levels, &render.palette(), render.brick_patterns()),
+            GameState::Active | GameState::Paused | GameState::Win | GameState::GameOver => {
+                let subtitle = match run.mode {
+                    GameMode::Classic => None,
+                    GameMode::Coop => Some(format!("Lives {}", run.lives.unwrap_or_default())),
+                    GameMode::Versus => Some(match &session {
+                        Some(session) => online_hud(session, &duel),
+                        None => format!("Right player {}", duel.right.score),
+                    }),
+                    GameMode::Endless => Some(format!("Seed {:08X}", run.seed)),
+                    GameMode::Daily if daily_scored => Some(daily::format_day(today)),
+                    GameMode::Daily => Some("Practice".to_owned()),
+                    GameMode::TimeAttack => Some(time_attack_hud(&run, levels.len())),
+                    GameMode::ScoreAttack => Some(format!(
+                        "Time left {}",
+                        format_time(run.time_left().unwrap_or_default()),
+                    )),
+                };
+                let subtitle = match (subtitle, assist_on) {
+                    (Some(x), true) => Some(x + "\nAssist"),
+                    (None, true) => Some("Assist".to_owned()),
+                    (x, false) => x,
+                };
+                ui.draw_hud(phys.score, subtitle.as_deref());
+
+                if run.over && !playtesting {
+                    ui.draw_board(&board);
+                }
+            },
+            GameState::PleaseRotate => (),
+        }
+        if !matches!(state, GameState::Start | GameState::Settings) {
+            sounder.direct_sounds(&game_model);
+        }
+        if split_screen {
+            sounder.direct_sounds(&rival_model);
+        }
+        sounder.direct_music(&game_model);
 
         next_frame().await
     }
-}
\ No newline at end of file
+
+    Ok(())
+}
+
+fn new_run(mode: GameMode, endless_seed: u64) -> Run {
+    let seed = match mode {
+        GameMode::Endless => endless_seed,
+        GameMode::Daily => daily::daily_seed(daily::today()),
+        GameMode::Classic | GameMode::TimeAttack | GameMode::ScoreAttack |
+        GameMode::Coop | GameMode::Versus => {
+            rand::rand() as u64
+        },
+    };
+
+    Run::new(mode, seed)
+}
+
+fn save_settings(settings: &Settings) {
+    if let Err(e) = settings.save() {
+        warn!("Failed to save the settings: {e}");
+    }
+}
+
+fn flush_save() {
+    if let Err(e) = save::flush() {
+        warn!("Failed to save the run: {e}");
+    }
+}
+
+fn online_hud(session: &Session, duel: &Match) -> String {
+    let mut res = format!("Right player {}", duel.right.score);
+    match session.side() {
+        Some(side) => res += &format!(
+            "\nYou are the {}, delay {}",
+            side.label().to_lowercase(),
+            session.delay(),
+        ),
+        None => res += "\nSpectating",
+    }
+
+    if session.current().is_none() {
+        res += "\nWaiting for the other side";
+    } else if session.outcome().is_none() && session.silence() > Duration::from_secs(1) {
+        res += "\nConnection trouble";
+    }
+
+    res
+}
+
+fn time_attack_hud(run: &Run, level_count: usize) -> String {
+    let mut res = format!(
+        "Stage {}/{}  {}",
+        (run.stage + 1).min(level_count),
+        level_count,
+        format_time(run.time),
+    );
+    if let Some(split) = run.last_split() {
+        res += &format!("\nLast split {}", format_time(split));
+    }
+
+    res
+}
+
+/// Writes the scored daily attempt down and submits it
+fn record_daily(
+    history: &mut DailyHistory,
+    leaderboard: &mut dyn LeaderboardBackend,
+    result: DailyResult,
+) {
+    let entry = LeaderboardEntry {
+        player: daily::player_name(),
+        day: result.day,
+        score: result.score,
+    };
+
+    if let Err(e) = history.record(result) {
+        warn!("Failed to save the daily history: {e}");
+    }
+    if let Err(e) = leaderboard.submit(entry) {
+        warn!("Failed to submit the daily score: {e}");
+    }
+}
+
+/// The best scores of the day, followed by the player's recent attempts
+fn daily_board_lines(
+    history: &DailyHistory,
+    leaderboard: &dyn LeaderboardBackend,
+    day: u64,
+) -> Vec<String> {
+    let mut lines = vec![format!("Daily {}", daily::format_day(day))];
+    match leaderboard.top(day, 5) {
+        Ok(top) => lines.extend(
+            top.iter()
+                .enumerate()
+                .map(|(idx, x)| format!("{}. {} {}", idx + 1, x.player, x.score))
+        ),
+        Err(e) => lines.push(format!("Leaderboard unavailable: {e}")),
+    }
+
+    lines.push("Your history".to_owned());
+    lines.extend(
+        history.results.iter().rev().take(5)
+            .map(|x| format!("{} {}", daily::format_day(x.day), x.score))
+    );
+
+    lines
+}
@@ -0,0 +1,243 @@
+use anyhow::{bail, Context};
+use macroquad::audio::{self, load_sound_from_bytes, PlaySoundParams, Sound};
+
+/// The rate the variants get rendered at, so that the backend doesn't resample them again
+const OUTPUT_RATE: u32 = 44100;
+/// Stereo positions every sound gets rendered at, from left to right
+pub const PAN_POSITIONS: [f32; 5] = [-1.0, -0.5, 0.0, 0.5, 1.0];
+/// How far apart the speakers are. Panning all the way to one side sounds harsh.
+const STEREO_WIDTH: f32 = 0.6;
+
+/// Mono samples in the range from -1 to 1
+pub struct Samples {
+    pub rate: u32,
+    pub data: Vec<f32>,
+}
+
+impl Samples {
+    /// Reads 16 bit PCM, which is what sfxr exports. Stereo gets mixed down.
+    pub fn from_wav(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            bail!("Not a wav file");
+        }
+
+        let mut format = None;
+        let mut rest = &bytes[12..];
+        while rest.len() >= 8 {
+            let id = &rest[0..4];
+            let len = u32::from_le_bytes(rest[4..8].try_into()?) as usize;
+            let body = rest.get(8..8 + len).context("Truncated wav chunk")?;
+
+            match id {
+                b"fmt " => {
+                    let field = |at: usize| u16::from_le_bytes([body[at], body[at + 1]]);
+                    if body.len() < 16 || field(0) != 1 || field(14) != 16 {
+                        bail!("Only 16 bit PCM is supported");
+                    }
+                    let rate = u32::from_le_bytes(body[4..8].try_into()?);
+                    format = Some((field(2).max(1) as usize, rate));
+                },
+                b"data" => {
+                    let (channels, rate) = format.context("The data comes before the format")?;
+                    let data = body.chunks_exact(2 * channels)
+                        .map(|frame| {
+                            frame.chunks_exact(2)
+                                .map(|x| i16::from_le_bytes([x[0], x[1]]) as f32 / 32768.0)
+                                .sum::<f32>() / channels as f32
+                        })
+                        .collect();
+
+                    return Ok(Self { rate, data });
+                },
+                _ => (),
+            }
+
+            // Chunks are padded to an even length
+            rest = rest.get(8 + len + len % 2..).unwrap_or_default();
+        }
+
+        bail!("No sample data")
+    }
+
+    /// Plays the samples faster or slower, which moves the pitch along with the length
+    pub fn pitched(&self, pitch: f32) -> Vec<f32> {
+        let step = pitch * self.rate as f32 / OUTPUT_RATE as f32;
+        let len = (self.data.len() as f32 / step) as usize;
+
+        (0..len).map(|idx| {
+            let pos = idx as f32 * step;
+            let at = pos as usize;
+            let frac = pos - at as f32;
+            let a = self.data.get(at).copied().unwrap_or_default();
+            let b = self.data.get(at + 1).copied().unwrap_or_default();
+
+            a + (b - a) * frac
+        }).collect()
+    }
+}
+
+/// Builds a stereo 16 bit wav out of mono samples, placed at `pan` from -1 (left) to 1 (right)
+pub fn encode_wav(data: &[f32], pan: f32) -> Vec<u8> {
+    // Equal power panning keeps the loudness the same across the field
+    let angle = (pan * STEREO_WIDTH + 1.0) * std::f32::consts::FRAC_PI_4;
+    let gains = [angle.cos(), angle.sin()];
+    let data_len = (data.len() * 4) as u32;
+
+    let mut res = Vec::with_capacity(44 + data_len as usize);
+    res.extend_from_slice(b"RIFF");
+    res.extend_from_slice(&(36 + data_len).to_le_bytes());
+    res.extend_from_slice(b"WAVEfmt ");
+    res.extend_from_slice(&16u32.to_le_bytes());
+    res.extend_from_slice(&1u16.to_le_bytes());
+    res.extend_from_slice(&2u16.to_le_bytes());
+    res.extend_from_slice(&OUTPUT_RATE.to_le_bytes());
+    res.extend_from_slice(&(OUTPUT_RATE * 4).to_le_bytes());
+    res.extend_from_slice(&4u16.to_le_bytes());
+    res.extend_from_slice(&16u16.to_le_bytes());
+    res.extend_from_slice(b"data");
+    res.extend_from_slice(&data_len.to_le_bytes());
+    for sample in data {
+        for gain in gains {
+            let value = (sample * gain * 32767.0).clamp(-32768.0, 32767.0) as i16;
+            res.extend_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    res
+}
+
+/// One sound effect rendered at every pan position and a set of pitches.
+/// macroquad can only change the volume of a sound, so every pan and pitch
+/// it may need gets a buffer of its own when the game starts.
+pub struct VariedSound {
+    pitches: Vec<f32>,
+    /// How long the sound plays at each pitch, in seconds
+    lengths: Vec<f32>,
+    /// Pitch after pitch, each at every pan position
+    variants: Vec<Sound>,
+}
+
+impl VariedSound {
+    pub async fn new(samples: &Samples, pitches: &[f32]) -> anyhow::Result<Self> {
+        let mut variants = Vec::with_capacity(pitches.len() * PAN_POSITIONS.len());
+        let mut lengths = Vec::with_capacity(pitches.len());
+        for &pitch in pitches {
+            let data = samples.pitched(pitch);
+            lengths.push(data.len() as f32 / OUTPUT_RATE as f32);
+
+            for pan in PAN_POSITIONS {
+                variants.push(load_sound_from_bytes(&encode_wav(&data, pan)).await?);
+            }
+        }
+
+        Ok(Self {
+            pitches: pitches.to_vec(),
+            lengths,
+            variants,
+        })
+    }
+
+    /// The index of the variant closest to the asked pan and pitch
+    pub fn pick(&self, pan: f32, pitch: f32) -> usize {
+        let closest = |values: &mut dyn Iterator<Item = f32>, target: f32| {
+            values.enumerate()
+                .min_by(|(_, a), (_, b)| (a - target).abs().total_cmp(&(b - target).abs()))
+                .map(|(idx, _)| idx)
+                .unwrap_or_default()
+        };
+        let pitch_idx = closest(&mut self.pitches.iter().map(|x| x.log2()), pitch.log2());
+        let pan_idx = closest(&mut PAN_POSITIONS.into_iter(), pan);
+
+        pitch_idx * PAN_POSITIONS.len() + pan_idx
+    }
+
+    pub fn variant(&self, idx: usize) -> &Sound {
+        &self.variants[idx]
+    }
+
+    /// How long the variant plays, in seconds
+    pub fn length(&self, idx: usize) -> f32 {
+        self.lengths[idx / PAN_POSITIONS.len()]
+    }
+}
+
+/// Something that tells apart the buffers of all the sounds. macroquad can only
+/// stop every playback of a buffer at once, so voices sharing one come and go together.
+pub type VoiceKey = (usize, usize, usize);
+
+/// An effect that is still playing
+struct Voice {
+    sound: Sound,
+    key: VoiceKey,
+    priority: u32,
+    ends_at: f64,
+}
+
+/// Keeps the number of effects playing at once in check, so that a chain of
+/// explosions doesn't clip. Once all the voices are taken, a new sound cuts off
+/// the least important one, the oldest among equals, or doesn't play at all.
+pub struct Voices {
+    max: usize,
+    playing: Vec<Voice>,
+}
+
+impl Voices {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            playing: Vec::with_capacity(max),
+        }
+    }
+
+    /// Plays the variant `idx` of the sound if it gets a voice. `forced` sounds always do,
+    /// whatever they have to cut off. Returns whether the sound started.
+    pub fn play(
+        &mut self,
+        sound: &VariedSound,
+        key: VoiceKey,
+        priority: u32,
+        volume: f32,
+        forced: bool,
+        now: f64,
+    ) -> bool {
+        self.playing.retain(|x| x.ends_at > now);
+
+        if self.playing.len() >= self.max {
+            let victim = self.playing.iter()
+                .filter(|x| forced || x.priority <= priority)
+                .min_by_key(|x| x.priority)
+                .map(|x| x.key);
+            let Some(victim) = victim else {
+                return false;
+            };
+
+            self.stop(victim);
+        }
+
+        let variant = sound.variant(key.2);
+        audio::play_sound(
+            variant,
+            PlaySoundParams {
+                looped: false,
+                volume,
+            }
+        );
+        self.playing.push(Voice {
+            sound: variant.clone(),
+            key,
+            priority,
+            ends_at: now + sound.length(key.2) as f64,
+        });
+
+        true
+    }
+
+    fn stop(&mut self, key: VoiceKey) {
+        let Some(voice) = self.playing.iter().find(|x| x.key == key) else {
+            return;
+        };
+
+        audio::stop_sound(&voice.sound);
+        self.playing.retain(|x| x.key != key);
+    }
+}
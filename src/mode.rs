@@ -0,0 +1,221 @@
+use nanoserde::{DeJson, SerJson};
+
+use crate::{daily, level::Level, levelgen, physics::Physics};
+
+pub use crate::serialized::Run;
+
+/// How long a Score Attack run lasts
+pub const SCORE_ATTACK_TIME: f32 = 180.0;
+/// How many balls the two players can drop together
+pub const COOP_LIVES: u32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeJson, SerJson)]
+pub enum GameMode {
+    /// The hand-made levels from the level index
+    Classic,
+    /// An infinite chain of generated levels
+    Endless,
+    /// One generated level a day, the same for everyone
+    Daily,
+    /// All hand-made levels in a row, against the clock
+    TimeAttack,
+    /// As many points as possible before the time runs out
+    ScoreAttack,
+    /// The hand-made levels with two paddles and shared lives
+    Coop,
+    /// Two players racing on the same generated level, side by side
+    Versus,
+}
+
+impl GameMode {
+    /// Every mode in the order the start menu lists them
+    pub const ALL: [GameMode; 7] = [
+        GameMode::Classic,
+        GameMode::Endless,
+        GameMode::Daily,
+        GameMode::TimeAttack,
+        GameMode::ScoreAttack,
+        GameMode::Coop,
+        GameMode::Versus,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            GameMode::Classic => "Classic",
+            GameMode::Endless => "Endless",
+            GameMode::Daily => "Daily",
+            GameMode::TimeAttack => "Time Attack",
+            GameMode::ScoreAttack => "Score Attack",
+            GameMode::Coop => "Co-op",
+            GameMode::Versus => "Versus",
+        }
+    }
+
+    /// How many stages make a complete run. `None` means the run
+    /// only ends once the player loses.
+    pub fn stage_count(self, level_count: usize) -> Option<usize> {
+        match self {
+            GameMode::Classic | GameMode::Endless | GameMode::ScoreAttack | GameMode::Coop => None,
+            GameMode::Daily | GameMode::Versus => Some(1),
+            GameMode::TimeAttack => Some(level_count),
+        }
+    }
+
+    pub fn time_limit(self) -> Option<f32> {
+        match self {
+            GameMode::ScoreAttack => Some(SCORE_ATTACK_TIME),
+            _ => None,
+        }
+    }
+
+    /// How many balls can be dropped before the run is lost, `None` means
+    /// dropping the ball just puts it back on the paddle
+    pub fn lives(self) -> Option<u32> {
+        match self {
+            GameMode::ScoreAttack => None,
+            GameMode::Coop => Some(COOP_LIVES),
+            _ => Some(1),
+        }
+    }
+
+    /// Whether the mode needs an input binding set for a second player
+    pub fn is_two_player(self) -> bool {
+        matches!(self, GameMode::Coop | GameMode::Versus)
+    }
+}
+
+/// What the mode makes of the frame that has just been simulated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Playing,
+    /// The ball got dropped, but the run goes on
+    Respawn,
+    /// The stage is cleared and the next one starts right away
+    Advance,
+    /// The stage is cleared and the next one starts once the player is ready
+    Cleared,
+    /// The run is complete
+    Finished,
+    Lost,
+}
+
+impl Run {
+    pub fn new(mode: GameMode, seed: u64) -> Self {
+        Self {
+            mode,
+            seed,
+            stage: 0,
+            lives: mode.lives(),
+            time: 0.0,
+            splits: Vec::new(),
+            over: false,
+        }
+    }
+
+    pub fn tick(&mut self, dt: f32) {
+        self.time += dt;
+    }
+
+    pub fn time_left(&self) -> Option<f32> {
+        self.mode.time_limit().map(|x| (x - self.time).max(0.0))
+    }
+
+    /// Applies the rules of the mode to what happened during the frame
+    pub fn judge(&mut self, cleared: bool, hit_floor: bool, level_count: usize) -> Verdict {
+        if self.time_left() == Some(0.0) {
+            self.over = true;
+            return Verdict::Finished;
+        }
+
+        if cleared {
+            self.splits.push(self.time);
+
+            if self.mode.stage_count(level_count) == Some(self.stage + 1) {
+                self.over = true;
+                return Verdict::Finished;
+            }
+
+            return match self.mode.time_limit() {
+                // The clock is ticking, no time for a break
+                Some(_) => Verdict::Advance,
+                None => Verdict::Cleared,
+            };
+        }
+
+        if hit_floor {
+            match &mut self.lives {
+                None => return Verdict::Respawn,
+                Some(lives) if *lives > 1 => {
+                    *lives -= 1;
+                    return Verdict::Respawn;
+                },
+                Some(_) => {
+                    self.over = true;
+                    return Verdict::Lost;
+                },
+            }
+        }
+
+        Verdict::Playing
+    }
+
+    pub fn next_stage(&mut self, level_count: usize) {
+        self.stage = match self.mode {
+            GameMode::Classic | GameMode::Coop => (self.stage + 1) % level_count,
+            _ => self.stage + 1,
+        };
+    }
+
+    /// Sets up the level of the current stage. Everything about it comes
+    /// from the run's seed, so that recorded runs play back the same.
+    pub fn stage_physics(&self, levels: &[Level]) -> Physics {
+        let stage = self.stage as u32;
+
+        match self.mode {
+            GameMode::Classic => Physics::from_level(
+                &levels[self.stage % levels.len()],
+                levelgen::chain_seed(self.seed, stage),
+            ),
+            // Every attempt gets the same enemies and drops, so that runs are comparable
+            GameMode::TimeAttack => Physics::from_level(
+                &levels[self.stage],
+                levelgen::chain_seed(0, stage),
+            ),
+            GameMode::Endless | GameMode::ScoreAttack | GameMode::Versus => Physics::from_level(
+                &levelgen::generate_chain(self.seed, stage),
+                levelgen::chain_seed(self.seed, stage),
+            ),
+            GameMode::Daily => Physics::from_level(
+                &levelgen::generate(self.seed, daily::DAILY_DIFFICULTY),
+                self.seed,
+            ),
+            GameMode::Coop => Physics::from_level(
+                &levels[self.stage % levels.len()],
+                levelgen::chain_seed(self.seed, stage),
+            ).with_partner(),
+        }
+    }
+
+    /// The hand-made level of the current stage, `None` for generated ones
+    pub fn stage_level<'a>(&self, levels: &'a [Level]) -> Option<&'a Level> {
+        match self.mode {
+            GameMode::Classic | GameMode::Coop => Some(&levels[self.stage % levels.len()]),
+            GameMode::TimeAttack => levels.get(self.stage),
+            GameMode::Endless | GameMode::ScoreAttack | GameMode::Versus | GameMode::Daily => None,
+        }
+    }
+
+    /// How long the last cleared stage took
+    pub fn last_split(&self) -> Option<f32> {
+        let (last, rest) = self.splits.split_last()?;
+
+        Some(last - rest.last().copied().unwrap_or(0.0))
+    }
+}
+
+/// Formats seconds as `M:SS.hh`
+pub fn format_time(secs: f32) -> String {
+    let hundredths = (secs * 100.0) as u32;
+
+    format!("{}:{:02}.{:02}", hundredths / 6000, hundredths / 100 % 60, hundredths % 100)
+}
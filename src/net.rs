@@ -0,0 +1,588 @@
+use std::{
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context};
+use nanoserde::{DeBin, SerBin};
+
+use crate::{
+    autopilot::Autopilot,
+    balance::{BOT_ERROR, BOT_REACTION},
+    input::{InputSource, InputView, PaddleInput},
+    level,
+    mode::{GameMode, Run},
+    versus::{Match, Side},
+};
+
+/// Online matches are simulated in fixed steps, so that both sides compute the same frames
+pub const TICK: f32 = 1.0 / 60.0;
+/// How many ticks late the local input gets applied. A bit of delay means
+/// fewer mispredictions to roll back.
+pub const DEFAULT_DELAY: u32 = 2;
+/// How far the simulation may run ahead of the last tick with the inputs
+/// of both players. Past that it waits for the other side.
+pub const MAX_ROLLBACK: u32 = 12;
+pub const CHECKSUM_EVERY: u32 = 30;
+/// How many of the own checksums are kept around for comparison
+pub const KEPT_CHECKSUMS: usize = 16;
+/// How many inputs go into a packet at most
+pub const MAX_BATCH: usize = 64;
+pub const MAX_PACKET: usize = 1024;
+/// How long a headless session keeps answering after the match is over,
+/// as the other side may still need the last inputs
+pub const LINGER_TIME: f32 = 1.0;
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// Plays the left side and lets the others in
+    Host { port: u16 },
+    /// Plays the right side
+    Guest { addr: String },
+    /// Only watches the host's match
+    Spectator { addr: String },
+}
+
+#[derive(Clone, Debug)]
+pub struct NetConfig {
+    pub role: Role,
+    pub delay: u32,
+    /// Let the autopilot play without a window
+    pub headless: bool,
+}
+
+impl NetConfig {
+    /// Reads `--host PORT`, `--join ADDR` or `--spectate ADDR`, along with
+    /// `--delay TICKS` and `--headless`. `None` means a local game.
+    pub fn from_args(args: &[String]) -> anyhow::Result<Option<Self>> {
+        let role = if let Some(port) = arg_value(args, "--host")? {
+            Role::Host { port: port.parse().context("Bad port")? }
+        } else if let Some(addr) = arg_value(args, "--join")? {
+            Role::Guest { addr: addr.to_owned() }
+        } else if let Some(addr) = arg_value(args, "--spectate")? {
+            Role::Spectator { addr: addr.to_owned() }
+        } else {
+            return Ok(None);
+        };
+        let delay = match arg_value(args, "--delay")? {
+            Some(x) => x.parse().context("Bad input delay")?,
+            None => DEFAULT_DELAY,
+        };
+
+        Ok(Some(Self {
+            role,
+            delay,
+            headless: args.iter().any(|x| x == "--headless"),
+        }))
+    }
+}
+
+fn arg_value<'a>(args: &'a [String], flag: &str) -> anyhow::Result<Option<&'a str>> {
+    let Some(idx) = args.iter().position(|x| x == flag) else {
+        return Ok(None);
+    };
+
+    match args.get(idx + 1) {
+        Some(x) => Ok(Some(x)),
+        None => bail!("{flag} needs a value"),
+    }
+}
+
+#[derive(Clone, Debug, DeBin, SerBin)]
+enum Packet {
+    /// Someone asking to join the host's match
+    Hello { spectator: bool },
+    Welcome { seed: u64 },
+    /// The sender's inputs from `start` on. `ack` is how many of the
+    /// receiver's inputs the sender already has.
+    Inputs { start: u32, inputs: Vec<PaddleInput>, ack: u32 },
+    /// What the sender's match looked like right before `tick`
+    Checksum { tick: u32, sum: u64 },
+    /// The inputs of both players from `start` on, for spectators
+    Stream { start: u32, inputs: Vec<[PaddleInput; 2]> },
+    /// How many ticks of the stream a spectator already has
+    Watching { ack: u32 },
+}
+
+/// One side of an online versus match with GGPO-style rollback. The match
+/// keeps going with a guess of what the other player does. Once the real
+/// inputs arrive, it gets simulated again from the last tick both sides
+/// agree on.
+///
+/// Works with native builds only, the web has no UDP.
+pub struct Session {
+    socket: UdpSocket,
+    /// Who the packets come from and go to. The host learns it from the first `Hello`.
+    peer: Option<SocketAddr>,
+    /// The local player's side, `None` for spectators
+    side: Option<Side>,
+    hosting: bool,
+    /// The spectators of the host's match and how much of the stream they have
+    spectators: Vec<(SocketAddr, u32)>,
+    seed: Option<u64>,
+    delay: u32,
+    /// Time that hasn't been simulated yet
+    acc: f32,
+    /// The known inputs of both sides, by tick
+    inputs: [Vec<PaddleInput>; 2],
+    /// How many of the local inputs the peer has
+    peer_ack: u32,
+    /// The match right before `confirmed_tick`, with nothing guessed
+    confirmed: Option<Match>,
+    confirmed_tick: u32,
+    /// The match right before `tick`, with the other side's input guessed
+    current: Option<Match>,
+    tick: u32,
+    /// Whether some guesses may have turned out wrong
+    rollback: bool,
+    checksums: Vec<(u32, u64)>,
+    peer_checksums: Vec<(u32, u64)>,
+    matched: u32,
+    last_heard: Instant,
+    outcome: Option<Side>,
+    desync: Option<u32>,
+}
+
+impl Session {
+    /// Opens the socket. The host's match is going to use `seed`, the
+    /// others get theirs from the host.
+    pub fn new(config: &NetConfig, seed: u64) -> anyhow::Result<Self> {
+        let (socket, peer, side) = match &config.role {
+            Role::Host { port } => (
+                UdpSocket::bind(("0.0.0.0", *port))?,
+                None,
+                Some(Side::Left),
+            ),
+            Role::Guest { addr } | Role::Spectator { addr } => {
+                let peer = addr.to_socket_addrs()?
+                    .next()
+                    .with_context(|| format!("{addr} doesn't resolve to anything"))?;
+                let any = if peer.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+                let side = match config.role {
+                    Role::Guest { .. } => Some(Side::Right),
+                    _ => None,
+                };
+
+                (UdpSocket::bind(any)?, Some(peer), side)
+            },
+        };
+        socket.set_nonblocking(true)?;
+        let hosting = matches!(config.role, Role::Host { .. });
+
+        Ok(Self {
+            socket,
+            peer,
+            side,
+            hosting,
+            spectators: Vec::new(),
+            seed: if hosting { Some(seed) } else { None },
+            delay: config.delay,
+            acc: 0.0,
+            inputs: [Vec::new(), Vec::new()],
+            peer_ack: 0,
+            confirmed: None,
+            confirmed_tick: 0,
+            current: None,
+            tick: 0,
+            rollback: false,
+            checksums: Vec::new(),
+            peer_checksums: Vec::new(),
+            matched: 0,
+            last_heard: Instant::now(),
+            outcome: None,
+            desync: None,
+        })
+    }
+
+    pub fn side(&self) -> Option<Side> {
+        self.side
+    }
+
+    pub fn delay(&self) -> u32 {
+        self.delay
+    }
+
+    /// The seed of the match, once everyone is there and it hasn't started yet
+    pub fn pending_seed(&self) -> Option<u64> {
+        self.seed.filter(|_| self.peer.is_some() && self.current.is_none())
+    }
+
+    pub fn start(&mut self, duel: Match) {
+        self.confirmed = Some(duel);
+        self.current = Some(duel);
+
+        // The first ticks are covered by the delay
+        if let Some(side) = self.side {
+            self.inputs[side.index()].resize(self.delay as usize, PaddleInput::default());
+        }
+    }
+
+    /// The match as the local player should see it
+    pub fn current(&self) -> Option<&Match> {
+        self.current.as_ref()
+    }
+
+    /// The winner, once the inputs of both players agree on one
+    pub fn outcome(&self) -> Option<Side> {
+        self.outcome
+    }
+
+    /// The tick at which the two sides stopped computing the same match
+    pub fn desync(&self) -> Option<u32> {
+        self.desync
+    }
+
+    /// How many checksums both sides agreed on
+    pub fn matched(&self) -> u32 {
+        self.matched
+    }
+
+    /// How long nothing came from the other side
+    pub fn silence(&self) -> Duration {
+        self.last_heard.elapsed()
+    }
+
+    /// Simulates the time that passed with the local player's input
+    pub fn advance(&mut self, input: PaddleInput, dt: f32) {
+        let Some(side) = self.side else { return; };
+        if self.current.is_none() || self.outcome.is_some() || self.desync.is_some() {
+            return;
+        }
+
+        self.acc += dt;
+        while self.acc >= TICK {
+            if self.tick >= self.confirmed_tick + MAX_ROLLBACK {
+                self.acc = 0.0;
+                break;
+            }
+
+            self.acc -= TICK;
+            self.inputs[side.index()].push(input);
+            let inputs = self.guess(self.tick);
+            if let Some(current) = &mut self.current {
+                current.step(inputs, TICK);
+            }
+            self.tick += 1;
+        }
+
+        self.send_inputs();
+    }
+
+    /// Handles everything that came in and keeps the other side posted.
+    /// Has to be called every frame, even once the match is over.
+    pub fn poll(&mut self) {
+        let mut buf = [0; MAX_PACKET];
+        while let Ok((len, from)) = self.socket.recv_from(&mut buf) {
+            if let Ok(packet) = Packet::deserialize_bin(&buf[..len]) {
+                self.handle(packet, from);
+            }
+        }
+
+        if let Some(peer) = self.peer {
+            match (self.seed, self.side) {
+                (None, side) => self.send(peer, &Packet::Hello { spectator: side.is_none() }),
+                (Some(_), None) => self.send(peer, &Packet::Watching {
+                    ack: self.inputs[0].len() as u32,
+                }),
+                (Some(_), Some(_)) => (),
+            }
+        }
+
+        self.confirm();
+        self.resimulate();
+        self.check_sums();
+        self.send_inputs();
+        self.send_stream();
+    }
+
+    fn handle(&mut self, packet: Packet, from: SocketAddr) {
+        match packet {
+            Packet::Hello { spectator: false } if self.hosting => {
+                // Only the first one gets to play
+                if self.peer.is_none() {
+                    self.peer = Some(from);
+                }
+                if self.peer == Some(from) {
+                    self.send(from, &Packet::Welcome { seed: self.seed.unwrap_or_default() });
+                }
+            },
+            Packet::Hello { spectator: true } if self.hosting => {
+                if !self.spectators.iter().any(|(x, _)| *x == from) {
+                    self.spectators.push((from, 0));
+                }
+                self.send(from, &Packet::Welcome { seed: self.seed.unwrap_or_default() });
+            },
+            Packet::Watching { ack } => {
+                if let Some((_, x)) = self.spectators.iter_mut().find(|(x, _)| *x == from) {
+                    *x = (*x).max(ack);
+                }
+            },
+            _ if self.peer != Some(from) => (),
+            Packet::Welcome { seed } => {
+                self.seed = self.seed.or(Some(seed));
+                self.last_heard = Instant::now();
+            },
+            Packet::Inputs { start, inputs, ack } => {
+                let Some(side) = self.side else { return; };
+                let other = &mut self.inputs[1 - side.index()];
+
+                self.peer_ack = self.peer_ack.max(ack);
+                if Self::extend(other, start, &inputs) {
+                    self.rollback = true;
+                }
+                self.last_heard = Instant::now();
+            },
+            Packet::Checksum { tick, sum } => {
+                self.peer_checksums.push((tick, sum));
+                self.last_heard = Instant::now();
+            },
+            Packet::Stream { start, inputs } if self.side.is_none() => {
+                let left = inputs.iter().map(|x| x[0]).collect::<Vec<_>>();
+                let right = inputs.iter().map(|x| x[1]).collect::<Vec<_>>();
+
+                Self::extend(&mut self.inputs[0], start, &left);
+                Self::extend(&mut self.inputs[1], start, &right);
+                self.last_heard = Instant::now();
+            },
+            _ => (),
+        }
+    }
+
+    /// Appends the part of `new` that isn't in `known` yet. Packets that
+    /// would leave a gap get dropped, the sender repeats them anyway.
+    fn extend(known: &mut Vec<PaddleInput>, start: u32, new: &[PaddleInput]) -> bool {
+        let Some(skip) = known.len().checked_sub(start as usize) else {
+            return false;
+        };
+        if skip >= new.len() {
+            return false;
+        }
+
+        known.extend_from_slice(&new[skip..]);
+        true
+    }
+
+    /// Guesses that players keep doing what they did last
+    fn guess(&self, tick: u32) -> [PaddleInput; 2] {
+        self.inputs.each_ref().map(|x| {
+            x.get(tick as usize).or(x.last()).copied().unwrap_or_default()
+        })
+    }
+
+    /// Moves the confirmed match forward as far as the inputs of both sides go
+    fn confirm(&mut self) {
+        let Some(confirmed) = &mut self.confirmed else { return; };
+        let mut checksums = Vec::new();
+
+        // Players keep the confirmed match behind the current one, so that
+        // it always has something to roll back to
+        while self.outcome.is_none() &&
+              (self.side.is_none() || self.confirmed_tick < self.tick) {
+            let tick = self.confirmed_tick as usize;
+            let (Some(left), Some(right)) = (self.inputs[0].get(tick), self.inputs[1].get(tick)) else {
+                break;
+            };
+
+            self.outcome = confirmed.step([*left, *right], TICK);
+            self.confirmed_tick += 1;
+            if self.confirmed_tick % CHECKSUM_EVERY == 0 {
+                checksums.push((self.confirmed_tick, confirmed.checksum()));
+            }
+        }
+
+        // The match is over for real, the guesses don't matter anymore
+        if self.outcome.is_some() || self.side.is_none() {
+            self.current = self.confirmed;
+        }
+
+        if let (Some(peer), Some(_)) = (self.peer, self.side) {
+            for (tick, sum) in checksums.iter().copied() {
+                self.send(peer, &Packet::Checksum { tick, sum });
+            }
+        }
+        self.checksums.extend(checksums);
+    }
+
+    /// Redoes the guessed ticks once the real inputs for some of them came in
+    fn resimulate(&mut self) {
+        if !self.rollback || self.outcome.is_some() {
+            return;
+        }
+        let Some(mut duel) = self.confirmed else { return; };
+
+        for tick in self.confirmed_tick..self.tick {
+            duel.step(self.guess(tick), TICK);
+        }
+        self.current = Some(duel);
+        self.rollback = false;
+    }
+
+    fn check_sums(&mut self) {
+        let latest = self.checksums.last().map_or(0, |x| x.0);
+
+        for (tick, sum) in self.peer_checksums.iter().copied() {
+            let Some((_, own)) = self.checksums.iter().find(|x| x.0 == tick) else {
+                continue;
+            };
+
+            if *own == sum {
+                self.matched += 1;
+            } else if self.desync.is_none() {
+                self.desync = Some(tick);
+            }
+        }
+
+        // Keep the ones that can't be compared yet
+        self.peer_checksums.retain(|x| x.0 > latest);
+        let extra = self.checksums.len().saturating_sub(KEPT_CHECKSUMS);
+        self.checksums.drain(..extra);
+    }
+
+    fn send_inputs(&self) {
+        let (Some(side), Some(peer), Some(_)) = (self.side, self.peer, self.current) else {
+            return;
+        };
+        let own = &self.inputs[side.index()];
+        let start = (self.peer_ack as usize).min(own.len());
+        let end = own.len().min(start + MAX_BATCH);
+
+        self.send(peer, &Packet::Inputs {
+            start: start as u32,
+            inputs: own[start..end].to_vec(),
+            ack: self.inputs[1 - side.index()].len() as u32,
+        });
+    }
+
+    fn send_stream(&self) {
+        let confirmed = self.confirmed_tick as usize;
+
+        for (addr, ack) in &self.spectators {
+            let start = (*ack as usize).min(confirmed);
+            let end = confirmed.min(start + MAX_BATCH);
+            let inputs = (start..end)
+                .map(|tick| [self.inputs[0][tick], self.inputs[1][tick]])
+                .collect();
+
+            self.send(*addr, &Packet::Stream { start: start as u32, inputs });
+        }
+    }
+
+    fn send(&self, addr: SocketAddr, packet: &Packet) {
+        // Packets get lost all the time anyway, everything important gets sent again
+        let _ = self.socket.send_to(&packet.serialize_bin(), addr);
+    }
+}
+
+/// Plays an online match without a window, with the autopilot steering the
+/// local paddle. Two of these on loopback make a quick desync check.
+pub fn run_headless(config: &NetConfig) -> anyhow::Result<()> {
+    let levels = level::read_levels()?;
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos() as u64;
+    let mut session = Session::new(config, seed)?;
+    let mut bot = Autopilot::new(seed, BOT_REACTION, BOT_ERROR);
+    let mut linger = 0.0;
+
+    loop {
+        session.poll();
+        if let Some(seed) = session.pending_seed() {
+            let run = Run::new(GameMode::Versus, seed);
+
+            session.start(Match::new(run.stage_physics(&levels)));
+        }
+
+        let input = match (session.current(), session.side()) {
+            (Some(duel), Some(side)) => bot.poll(&InputView {
+                phys: duel.field(side),
+                dt: TICK,
+                two_players: false,
+            }).player,
+            _ => PaddleInput::default(),
+        };
+        session.advance(input, TICK);
+
+        if let Some(tick) = session.desync() {
+            bail!("Desync at tick {tick}");
+        }
+        if session.current().is_some() && session.outcome().is_none() && session.silence() > TIMEOUT {
+            bail!("The other side stopped answering");
+        }
+        if let Some(winner) = session.outcome() {
+            match session.side() {
+                Some(_) if linger == 0.0 => println!(
+                    "{} wins, {} checksums matched",
+                    winner.label(),
+                    session.matched(),
+                ),
+                None if linger == 0.0 => println!("{} wins", winner.label()),
+                _ => (),
+            }
+
+            linger += TICK;
+            if linger >= LINGER_TIME {
+                return Ok(());
+            }
+        }
+
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(role: Role) -> NetConfig {
+        NetConfig {
+            role,
+            delay: DEFAULT_DELAY,
+            headless: true,
+        }
+    }
+
+    /// Each side sweeps its paddle back and forth at its own pace
+    fn input(session: &Session) -> PaddleInput {
+        let period = match session.side() {
+            Some(Side::Left) => 40,
+            _ => 55,
+        };
+        let right = session.tick / period % 2 == 0;
+
+        PaddleInput { left: !right, right }
+    }
+
+    #[test]
+    fn loopback_sessions_agree() {
+        let levels = level::read_levels().unwrap();
+        let mut host = Session::new(&config(Role::Host { port: 0 }), 99).unwrap();
+        let port = host.socket.local_addr().unwrap().port();
+        let mut guest = Session::new(&config(Role::Guest { addr: format!("127.0.0.1:{port}") }), 0).unwrap();
+
+        for frame in 0..2000 {
+            for session in [&mut host, &mut guest] {
+                session.poll();
+                if let Some(seed) = session.pending_seed() {
+                    let run = Run::new(GameMode::Versus, seed);
+                    session.start(Match::new(run.stage_physics(&levels)));
+                }
+
+                // The last frames only let the inputs catch up
+                if frame < 1500 {
+                    session.advance(input(session), TICK);
+                }
+            }
+            std::thread::sleep(Duration::from_micros(100));
+        }
+
+        assert_eq!(host.desync(), None);
+        assert_eq!(guest.desync(), None);
+        assert!(host.matched() > 0 && guest.matched() > 0);
+        assert_eq!(host.outcome(), guest.outcome());
+        assert!(host.confirmed_tick > 0);
+        assert_eq!(host.confirmed_tick, guest.confirmed_tick);
+        assert_eq!(
+            host.confirmed.map(|x| x.checksum()),
+            guest.confirmed.map(|x| x.checksum()),
+        );
+    }
+}
@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+
+use anyhow::bail;
+use macroquad::prelude::*;
+use macroquad_particles::{self as particles, BlendMode, ColorCurve, EmitterConfig};
+use nanoserde::DeJson;
+
+use crate::{
+    boss::PROJECTILE_RADIUS,
+    enemy::ENEMY_RADIUS,
+    physics::{BALL_RADIUS, BOX_HEIGHT, BOX_WIDTH},
+    theme::parse_color,
+};
+
+pub use crate::serialized::ParticleStyle;
+
+pub const LIBRARY_PATH: &str = "assets/particles.json";
+/// How many one-shot effects can play at once
+const MAX_SHOTS: usize = 32;
+
+/// The particle effects of the arena
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Effect {
+    Trail,
+    Explosion,
+    BallExplosion,
+    EnemyExplosion,
+    BossExplosion,
+    PaddleSpark,
+}
+
+impl Effect {
+    pub const ALL: [Effect; 6] = [
+        Effect::Trail,
+        Effect::Explosion,
+        Effect::BallExplosion,
+        Effect::EnemyExplosion,
+        Effect::BossExplosion,
+        Effect::PaddleSpark,
+    ];
+
+    /// The key of the effect in the library and in theme files
+    pub fn name(self) -> &'static str {
+        match self {
+            Effect::Trail => "trail",
+            Effect::Explosion => "explosion",
+            Effect::BallExplosion => "ball_explosion",
+            Effect::EnemyExplosion => "enemy_explosion",
+            Effect::BossExplosion => "boss_explosion",
+            Effect::PaddleSpark => "paddle_spark",
+        }
+    }
+
+    fn by_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.name() == name)
+    }
+
+    /// Whether the particles are drawn with the particle texture instead of as squares
+    pub fn textured(self) -> bool {
+        matches!(self, Effect::BallExplosion | Effect::BossExplosion | Effect::PaddleSpark)
+    }
+
+    /// What the effect looks like when the library leaves it out
+    fn builtin(self) -> EmitterConfig {
+        match self {
+            Effect::Trail => trail(),
+            Effect::Explosion => explosion(),
+            Effect::BallExplosion => ball_explosion(),
+            Effect::EnemyExplosion => enemy_explosion(),
+            Effect::BossExplosion => boss_explosion(),
+            Effect::PaddleSpark => paddle_spark(),
+        }
+    }
+}
+
+fn trail() -> particles::EmitterConfig {
+    particles::EmitterConfig {
+        emitting: true,
+        lifetime: 1.2,
+        lifetime_randomness: 0.7,
+        explosiveness: 0.01,
+        amount: 15,
+        initial_direction_spread: 0.4 * std::f32::consts::PI,
+        initial_velocity: 100.0,
+        size: 1.0,
+        gravity: vec2(0.0, 1000.0),
+        blend_mode: BlendMode::Alpha,
+        emission_shape: macroquad_particles::EmissionShape::Sphere { radius: BALL_RADIUS },
+        colors_curve: ColorCurve {
+            start: Color::from_hex(0xDDFBFF),
+            mid: BLANK,
+            end: BLANK,
+        },
+        ..Default::default()
+    }
+}
+
+fn explosion() -> particles::EmitterConfig {
+    particles::EmitterConfig {
+        one_shot: true,
+        emitting: false,
+        lifetime: 0.3,
+        lifetime_randomness: 0.7,
+        explosiveness: 0.99,
+        amount: 30,
+        initial_direction_spread: 2.0 * std::f32::consts::PI,
+        initial_velocity: 200.0,
+        size: 1.5,
+        gravity: vec2(0.0, 1000.0),
+        blend_mode: BlendMode::Alpha,
+        emission_shape: macroquad_particles::EmissionShape::Rect {
+            width: BOX_WIDTH,
+            height: BOX_HEIGHT,
+        },
+        colors_curve: ColorCurve {
+            start: Color::from_hex(0x333354),
+            mid: Color::from_hex(0x333354),
+            end: BLACK,
+        },
+        ..Default::default()
+    }
+}
+
+fn ball_explosion() -> particles::EmitterConfig {
+    particles::EmitterConfig {
+        one_shot: true,
+        emitting: false,
+        lifetime: 1.0,
+        lifetime_randomness: 0.7,
+        explosiveness: 0.99,
+        amount: 10,
+        initial_direction_spread: 2.0 * std::f32::consts::PI,
+        initial_velocity: 100.0,
+        size: 20.0,
+        gravity: vec2(0.0, -1000.0),
+        blend_mode: BlendMode::Alpha,
+        emission_shape: macroquad_particles::EmissionShape::Sphere { radius: BALL_RADIUS * 4.0 },
+        initial_angular_velocity: 5.0,
+        angular_accel: 0.0,
+        angular_damping: 0.01,
+        colors_curve: ColorCurve {
+            start: Color::from_hex(0xDDFBFF),
+            mid: Color { r: 1.0, g: 0.0, b: 0.0, a: 0.0, },
+            end: BLANK,
+        },
+        ..Default::default()
+    }
+}
+
+fn enemy_explosion() -> particles::EmitterConfig {
+    particles::EmitterConfig {
+        one_shot: true,
+        emitting: false,
+        lifetime: 0.5,
+        lifetime_randomness: 0.5,
+        explosiveness: 0.99,
+        amount: 20,
+        initial_direction_spread: 2.0 * std::f32::consts::PI,
+        initial_velocity: 120.0,
+        size: 2.0,
+        gravity: vec2(0.0, 300.0),
+        blend_mode: BlendMode::Alpha,
+        emission_shape: macroquad_particles::EmissionShape::Sphere { radius: ENEMY_RADIUS },
+        colors_curve: ColorCurve {
+            start: Color::from_hex(0xFFD27F),
+            mid: Color::from_hex(0xDDFBFF),
+            end: BLANK,
+        },
+        ..Default::default()
+    }
+}
+
+fn boss_explosion() -> particles::EmitterConfig {
+    particles::EmitterConfig {
+        amount: 40,
+        size: 24.0,
+        initial_velocity: 160.0,
+        emission_shape: macroquad_particles::EmissionShape::Rect {
+            width: crate::boss::BOSS_WIDTH,
+            height: crate::boss::BOSS_HEIGHT,
+        },
+        ..ball_explosion()
+    }
+}
+
+fn paddle_spark() -> particles::EmitterConfig {
+    particles::EmitterConfig {
+        amount: 12,
+        size: 6.0,
+        lifetime: 0.4,
+        gravity: vec2(0.0, -300.0),
+        emission_shape: macroquad_particles::EmissionShape::Sphere { radius: PROJECTILE_RADIUS },
+        ..ball_explosion()
+    }
+}
+
+impl ParticleStyle {
+    fn apply(&self, config: &mut EmitterConfig) -> anyhow::Result<()> {
+        if let Some(x) = self.amount {
+            config.amount = x;
+        }
+        if let Some(x) = self.lifetime {
+            config.lifetime = x;
+        }
+        if let Some(x) = self.lifetime_randomness {
+            config.lifetime_randomness = x;
+        }
+        if let Some(x) = self.explosiveness {
+            config.explosiveness = x;
+        }
+        if let Some(x) = self.initial_velocity {
+            config.initial_velocity = x;
+        }
+        if let Some(x) = self.initial_velocity_randomness {
+            config.initial_velocity_randomness = x;
+        }
+        if let Some(x) = self.initial_direction_spread {
+            config.initial_direction_spread = x;
+        }
+        if let Some(x) = self.initial_angular_velocity {
+            config.initial_angular_velocity = x;
+        }
+        if let Some(x) = self.size {
+            config.size = x;
+        }
+        if let Some(x) = self.size_randomness {
+            config.size_randomness = x;
+        }
+        if let Some(gravity) = &self.gravity {
+            let [x, y] = gravity[..] else {
+                bail!("The gravity takes an x and a y");
+            };
+            config.gravity = vec2(x, y);
+        }
+        if let Some(additive) = self.additive {
+            config.blend_mode = if additive { BlendMode::Additive } else { BlendMode::Alpha };
+        }
+        if let Some(colors) = &self.colors {
+            let [start, mid, end] = &colors[..] else {
+                bail!("The colours are a start, a middle and an end");
+            };
+            config.colors_curve = ColorCurve {
+                start: parse_color(start)?,
+                mid: parse_color(mid)?,
+                end: parse_color(end)?,
+            };
+        }
+
+        Ok(())
+    }
+}
+
+/// Changes `configs`, which are in the order of [Effect::ALL], by the styles.
+/// Unknown effects and broken styles get a warning that names `source`.
+pub fn apply_styles(configs: &mut [EmitterConfig], styles: &HashMap<String, ParticleStyle>, source: &str) {
+    for (key, style) in styles {
+        let Some(effect) = Effect::by_name(key) else {
+            warn!("{source} has an unknown particle effect {key:?}");
+            continue;
+        };
+        if let Err(e) = style.apply(&mut configs[effect as usize]) {
+            warn!("{source}, particle effect {key}: {e}");
+        }
+    }
+}
+
+/// The effects as the library describes them, in the order of [Effect::ALL].
+/// Whatever the library leaves out or gets wrong looks like the built-in effect.
+pub async fn load_library() -> Vec<EmitterConfig> {
+    let mut res = Effect::ALL.map(Effect::builtin).to_vec();
+    let styles = match load_string(LIBRARY_PATH).await {
+        Ok(src) => HashMap::<String, ParticleStyle>::deserialize_json(&src).unwrap_or_else(|e| {
+            warn!("Using the built-in particle effects, {LIBRARY_PATH} is broken: {e}");
+            HashMap::new()
+        }),
+        Err(e) => {
+            warn!("Using the built-in particle effects: {e}");
+            HashMap::new()
+        },
+    };
+    apply_styles(&mut res, &styles, LIBRARY_PATH);
+
+    res
+}
+
+/// A one-shot effect that is still playing
+struct Shot {
+    effect: Effect,
+    emitter: particles::Emitter,
+    pos: Vec2,
+    /// Seconds until the last particle is gone
+    left: f32,
+}
+
+/// Plays one-shot effects, each one at its own position. Setting an
+/// emitter up is expensive, so the finished ones wait for the next
+/// effect of the same kind.
+pub struct ParticleFx {
+    /// In the order of [Effect::ALL]
+    configs: Vec<EmitterConfig>,
+    /// The oldest first
+    shots: Vec<Shot>,
+    idle: Vec<(Effect, particles::Emitter)>,
+}
+
+impl ParticleFx {
+    pub fn new(configs: Vec<EmitterConfig>) -> Self {
+        Self {
+            configs,
+            shots: Vec::with_capacity(MAX_SHOTS),
+            idle: Vec::with_capacity(MAX_SHOTS),
+        }
+    }
+
+    /// Switches to other settings for the effects. The ones going on are cut short.
+    pub fn set_configs(&mut self, configs: Vec<EmitterConfig>) {
+        self.configs = configs;
+        self.shots.clear();
+        self.idle.clear();
+    }
+
+    /// Starts the effect at `pos`. With too many effects going on the oldest one makes way.
+    pub fn spawn(&mut self, effect: Effect, pos: Vec2) {
+        if self.shots.len() >= MAX_SHOTS {
+            let oldest = self.shots.remove(0);
+            self.retire(oldest);
+        }
+
+        let config = &self.configs[effect as usize];
+        let mut emitter = match self.idle.iter().position(|(x, _)| *x == effect) {
+            Some(idx) => self.idle.swap_remove(idx).1,
+            None => particles::Emitter::new(config.clone()),
+        };
+        emitter.config.emitting = true;
+
+        self.shots.push(Shot {
+            effect,
+            emitter,
+            pos,
+            // The particles come out over the part of the lifetime that
+            // the explosiveness leaves, and each lives up to a lifetime
+            left: config.lifetime * (2.0 - config.explosiveness),
+        });
+    }
+
+    pub fn draw(&mut self, dt: f32) {
+        for shot in &mut self.shots {
+            shot.emitter.draw(shot.pos);
+            shot.left -= dt;
+        }
+
+        // Finished shots move over to the idle ones in place. The shots stay
+        // oldest first, which is the order they get taken over in.
+        let mut idx = 0;
+        while idx < self.shots.len() {
+            if self.shots[idx].left <= 0.0 {
+                let shot = self.shots.remove(idx);
+                self.retire(shot);
+            } else {
+                idx += 1;
+            }
+        }
+    }
+
+    fn retire(&mut self, shot: Shot) {
+        if self.idle.len() >= MAX_SHOTS {
+            self.idle.remove(0);
+        }
+        self.idle.push((shot.effect, shot.emitter));
+    }
+}
@@ -1,4 +1,16 @@
 use macroquad::prelude::*;
+use nanoserde::{DeJson, SerJson};
+
+use crate::{
+    boss::{Boss, ProjectileKind, BOSS_HIT_SCORE, BOSS_KILL_SCORE},
+    checksum::Checksum,
+    enemy::{Enemies, ENEMY_RADIUS, ENEMY_SCORE},
+    level::{Level, LevelKind},
+    powerup::{Drops, PowerUp},
+    rng::Rng,
+};
+
+pub use crate::serialized::Physics;
 
 pub const PUSH_EPSILON: f32 = 0.001;
 pub const PLAYER_SPEED: f32 = 256.0;
@@ -13,37 +25,136 @@ pub const MAX_Y: f32 = 410.0;
 pub const PLAYER_WIDTH: f32 = 80.0;
 pub const PLAYER_HEIGHT: f32 = 10.0;
 pub const BALL_NUDGE: f32 = 0.4;
+pub const BOX_SCORE: u32 = 10;
+pub const SHRINK_TIME: f32 = 6.0;
+pub const SHRINK_FACTOR: f32 = 0.6;
+pub const STUN_TIME: f32 = 1.0;
+pub const WARP_TIME: f32 = 8.0;
+pub const WARP_BONUS: u32 = 1000;
+pub const GARBAGE_GAP: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeJson, SerJson)]
+pub enum Brick {
+    Empty,
+    Normal,
+    /// Takes two hits. Becomes `Cracked` after the first one.
+    Tough,
+    Cracked,
+    /// Can't be broken at all
+    Solid,
+    /// Takes its neighbours with it
+    Explosive,
+}
+
+impl Brick {
+    /// Every brick type in the order the editor offers them
+    pub const ALL: [Brick; 6] = [
+        Brick::Empty,
+        Brick::Normal,
+        Brick::Tough,
+        Brick::Cracked,
+        Brick::Solid,
+        Brick::Explosive,
+    ];
+
+    pub fn from_char(ch: char) -> Option<Self> {
+        Brick::ALL.into_iter().find(|x| x.to_char() == ch)
+    }
+
+    pub fn to_char(self) -> char {
+        match self {
+            Brick::Empty => '.',
+            Brick::Normal => 'B',
+            Brick::Tough => 'T',
+            Brick::Cracked => 't',
+            Brick::Solid => 'S',
+            Brick::Explosive => 'X',
+        }
+    }
 
-#[derive(Clone, Copy, Debug)]
-pub struct Physics {
-    pub player_x: f32,
-    pub player_delta: f32,
-    pub ball_pos: Vec2,
-    pub ball_dir: Vec2,
-    pub boxes: [[bool; BOX_PER_LINE]; BOX_LINE_COUNT],
+    /// Whether the player needs to break this brick to win
+    pub fn is_breakable(self) -> bool {
+        !matches!(self, Brick::Empty | Brick::Solid)
+    }
 }
 
 impl Physics {
-    pub fn new() -> Self {
+    /// Sets up the level. All randomness of the simulation comes from `seed`.
+    pub fn from_level(level: &Level, seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
         let player_start = MAX_X / 2.0 - PLAYER_WIDTH / 2.0;
-        let mut boxes = [[true; BOX_PER_LINE]; BOX_LINE_COUNT];
-        for x in 0..BOX_PER_LINE {
-            boxes[0][x] = false;
-        }
+        let boss = match level.kind {
+            LevelKind::Bricks => None,
+            LevelKind::Boss => Some(Boss::new(rng.next_u64())),
+        };
 
         Self {
             player_x: player_start,
             player_delta: 0.0,
-            ball_pos: vec2(
-                player_start + PLAYER_WIDTH / 2.0,
-                MAX_Y - PLAYER_HEIGHT - BALL_RADIUS * 1.9 - BALL_RADIUS - PUSH_EPSILON
-            ),
+            partner_x: None,
+            partner_delta: 0.0,
+            ball_pos: Self::ball_start(player_start + PLAYER_WIDTH / 2.0),
             ball_dir: vec2(-1.0, -1.0).normalize(),
-            boxes,
+            ball_speed: level.ball_speed,
+            boxes: level.bricks,
+            enemies: Enemies::new(rng.next_u64(), level.enemies),
+            boss,
+            shrink_timer: 0.0,
+            stun_timer: 0.0,
+            drops: Drops::new(rng.next_u64()),
+            warp_timer: 0.0,
+            warped: false,
+            score: 0,
+            broken: 0,
+            combo: 0,
+            garbage_rows: 0,
+            rng: Rng::new(rng.next_u64()),
         }
     }
 
+    /// Splits the baseline between two paddles
+    pub fn with_partner(mut self) -> Self {
+        self.player_x = MAX_X / 3.0 - PLAYER_WIDTH / 2.0;
+        self.partner_x = Some(MAX_X * 2.0 / 3.0 - PLAYER_WIDTH / 2.0);
+        self.respawn_ball();
+
+        self
+    }
+
+    pub fn checksum(&self, sum: &mut Checksum) {
+        sum.f32(self.player_x);
+        sum.f32(self.player_delta);
+        sum.bool(self.partner_x.is_some());
+        sum.f32(self.partner_x.unwrap_or_default());
+        sum.f32(self.partner_delta);
+        sum.vec2(self.ball_pos);
+        sum.vec2(self.ball_dir);
+        sum.f32(self.ball_speed);
+        for brick in self.boxes.iter().flatten() {
+            sum.u32(*brick as u32);
+        }
+        self.enemies.checksum(sum);
+        sum.bool(self.boss.is_some());
+        if let Some(boss) = &self.boss {
+            boss.checksum(sum);
+        }
+        sum.f32(self.shrink_timer);
+        sum.f32(self.stun_timer);
+        self.drops.checksum(sum);
+        sum.f32(self.warp_timer);
+        sum.bool(self.warped);
+        sum.u32(self.score);
+        sum.u32(self.broken);
+        sum.u32(self.combo);
+        sum.u32(self.garbage_rows);
+        self.rng.checksum(sum);
+    }
+
     pub fn move_player(&mut self, dt: f32, right: bool) {
+        if self.stun_timer > 0.0 {
+            return;
+        }
+
         let mut dx = PLAYER_SPEED * dt;
         if !right { dx *= -1.0; }
 
@@ -51,15 +162,110 @@ impl Physics {
         self.player_delta = dx;
     }
 
+    /// Puts the ball back on top of the paddle, like at the start of the level
+    pub fn respawn_ball(&mut self) {
+        self.ball_pos = Self::ball_start(self.player_rect().center().x);
+        self.ball_dir = vec2(-1.0, -1.0).normalize();
+        self.combo = 0;
+    }
+
+    pub fn move_partner(&mut self, dt: f32, right: bool) {
+        let Some(partner_x) = &mut self.partner_x else { return; };
+        if self.stun_timer > 0.0 {
+            return;
+        }
+
+        let mut dx = PLAYER_SPEED * dt;
+        if !right { dx *= -1.0; }
+
+        *partner_x += dx;
+        self.partner_delta = dx;
+    }
+
+    /// Shifts the bricks one row down and fills the top row with normal
+    /// bricks, leaving a gap. Returns false without touching the bricks
+    /// while there is no room: the bottom row still has bricks in it, or
+    /// the ball is among the bricks and the shifted rows would land on it.
+    pub fn push_garbage_row(&mut self) -> bool {
+        let bottom_full = self.boxes[BOX_LINE_COUNT - 1].iter().any(|x| *x != Brick::Empty);
+        let ball_inside = self.ball_pos.y - BALL_RADIUS < BOX_LINE_COUNT as f32 * BOX_HEIGHT;
+        if bottom_full || ball_inside {
+            return false;
+        }
+
+        let gap = self.rng.below((BOX_PER_LINE - GARBAGE_GAP) as u32 + 1) as usize;
+
+        self.boxes.copy_within(0..BOX_LINE_COUNT - 1, 1);
+        self.boxes[0] = [Brick::Normal; BOX_PER_LINE];
+        self.boxes[0][gap..gap + GARBAGE_GAP].fill(Brick::Empty);
+        self.garbage_rows += 1;
+
+        true
+    }
+
     pub fn new_frame(&mut self) {
         self.player_delta = 0.0;
+        self.partner_delta = 0.0;
     }
 
     pub fn update(&mut self, dt: f32) -> bool {
-        let offset = self.ball_dir * BALL_SPEED * dt;
+        let offset = self.ball_dir * self.ball_speed * dt;
         let mut new_ball_pos = self.ball_pos + offset;
 
-        self.player_x = self.player_x.clamp(0.0, MAX_X - PLAYER_WIDTH);
+        // While the warp gate is open, the paddle may leave through the right wall
+        let max_player_x = if self.warp_timer > 0.0 {
+            MAX_X
+        } else {
+            MAX_X - PLAYER_WIDTH
+        };
+        match &mut self.partner_x {
+            Some(partner_x) => {
+                self.player_x = self.player_x.clamp(0.0, MAX_X - PLAYER_WIDTH);
+                *partner_x = partner_x.clamp(0.0, max_player_x);
+
+                // The paddles can't pass through each other. Whoever pushed
+                // into the other one gets stopped.
+                let overlap = self.player_x + PLAYER_WIDTH - *partner_x;
+                if overlap > 0.0 {
+                    let (mine, theirs) = match (self.player_delta > 0.0, self.partner_delta < 0.0) {
+                        (true, true) => (0.5, 0.5),
+                        (false, true) => (0.0, 1.0),
+                        _ => (1.0, 0.0),
+                    };
+
+                    self.player_x -= overlap * mine;
+                    *partner_x += overlap * theirs;
+                }
+            },
+            None => self.player_x = self.player_x.clamp(0.0, max_player_x),
+        }
+
+        // Only the rightmost paddle can reach the warp gate
+        let rightmost_x = self.partner_x.unwrap_or(self.player_x);
+        if !self.warped && rightmost_x + PLAYER_WIDTH / 2.0 > MAX_X {
+            self.warped = true;
+            self.score += WARP_BONUS;
+        }
+
+        self.warp_timer = (self.warp_timer - dt).max(0.0);
+        self.shrink_timer = (self.shrink_timer - dt).max(0.0);
+        self.stun_timer = (self.stun_timer - dt).max(0.0);
+
+        // There are two paddles at most, so they fit in an array without allocating
+        let player = self.player_rect();
+        let partner = self.partner_rect();
+        let paddle_count = 1 + partner.is_some() as usize;
+        let paddle_array = [player, partner.unwrap_or(player)];
+        let paddles = &paddle_array[..paddle_count];
+        let boxes = &self.boxes;
+        self.enemies.update(
+            dt,
+            &paddle_array.map(|x| x.center())[..paddle_count],
+            |pos| Self::enemy_blocked(boxes, pos),
+        );
+        for rect in self.paddle_rects() {
+            self.score += self.enemies.hit_by_rect(rect) * ENEMY_SCORE;
+        }
 
         if new_ball_pos.x - BALL_RADIUS < 0.0 {
             self.ball_dir.x *= -1.0;
@@ -86,7 +292,7 @@ impl Physics {
             for bx in 0..BOX_PER_LINE {
                 let box_rect = Self::box_rect(bx, by);
 
-                if !self.boxes[by][bx] {
+                if self.boxes[by][bx] == Brick::Empty {
                     continue;
                 }
 
@@ -94,33 +300,139 @@ impl Physics {
                     continue;
                 }
 
-                self.boxes[by][bx] = false;
-
-                if Self::ball_bumped_vertically(self.ball_pos, box_rect) {
-                    self.ball_dir.y *= -1.0;
-                    if self.ball_pos.y > box_rect.bottom() {
-                        new_ball_pos.y = box_rect.bottom() + BALL_RADIUS + PUSH_EPSILON;
-                    } else {
-                        new_ball_pos.y = box_rect.top() - BALL_RADIUS - PUSH_EPSILON;
-                    }
-                } else {
-                    self.ball_dir.x *= -1.0;
-                    if self.ball_pos.x > box_rect.right() {
-                        new_ball_pos.x = box_rect.right() + BALL_RADIUS + PUSH_EPSILON;
-                    } else {
-                        new_ball_pos.x = box_rect.left() - BALL_RADIUS - PUSH_EPSILON;
-                    }
+                match self.boxes[by][bx] {
+                    Brick::Solid => (),
+                    Brick::Tough => self.boxes[by][bx] = Brick::Cracked,
+                    _ => self.break_box(bx, by),
+                }
+                self.bounce_off_rect(&mut new_ball_pos, box_rect);
+            }
+        }
+
+        let player_rect = self.player_rect();
+        let caught = self.drops.update(dt, player_rect).or_else(|| {
+            let partner_rect = self.partner_rect()?;
+            self.drops.update(0.0, partner_rect)
+        });
+        match caught {
+            Some(PowerUp::Warp) => self.warp_timer = WARP_TIME,
+            None => (),
+        }
+
+        if let Some(boss) = &mut self.boss {
+            // The paddles share their timers, so a hit on either slows both down
+            match boss.update(dt, paddles) {
+                Some(ProjectileKind::Shrink) => self.shrink_timer = SHRINK_TIME,
+                Some(ProjectileKind::Stun) => self.stun_timer = STUN_TIME,
+                None => (),
+            }
+        }
+
+        if let Some(boss_rect) = self.boss.filter(|x| x.is_alive()).map(|x| x.rect()) {
+            if Self::ball_in_rect(new_ball_pos, boss_rect) {
+                self.bounce_off_rect(&mut new_ball_pos, boss_rect);
+
+                let boss = self.boss.as_mut().unwrap();
+                self.score += BOSS_HIT_SCORE;
+                if boss.hit() {
+                    self.score += BOSS_KILL_SCORE;
                 }
             }
         }
 
+        // Enemies are round, so the ball just gets reflected off them
+        if let Some(enemy_pos) = self.enemies.hit_by_circle(new_ball_pos, BALL_RADIUS) {
+            let normal = (new_ball_pos - enemy_pos).normalize_or_zero();
+            let along_normal = self.ball_dir.dot(normal);
+
+            if along_normal < 0.0 {
+                self.ball_dir -= 2.0 * along_normal * normal;
+            }
+            self.score += ENEMY_SCORE;
+        }
+
         let player_rect = self.player_rect();
+        self.bounce_off_paddle(&mut new_ball_pos, player_rect, self.player_delta);
+        if let Some(partner_rect) = self.partner_rect() {
+            self.bounce_off_paddle(&mut new_ball_pos, partner_rect, self.partner_delta);
+        }
+
+        self.ball_pos = new_ball_pos;
+
+        false
+    }
+
+    pub fn player_rect(&self) -> Rect {
+        self.paddle_rect(self.player_x)
+    }
+
+    pub fn partner_rect(&self) -> Option<Rect> {
+        self.partner_x.map(|x| self.paddle_rect(x))
+    }
+
+    /// Every paddle in play
+    pub fn paddle_rects(&self) -> impl Iterator<Item = Rect> {
+        std::iter::once(self.player_rect()).chain(self.partner_rect())
+    }
+
+    fn paddle_rect(&self, x: f32) -> Rect {
+        // A shrunk paddle keeps its center
+        let width = if self.shrink_timer > 0.0 {
+            PLAYER_WIDTH * SHRINK_FACTOR
+        } else {
+            PLAYER_WIDTH
+        };
+
+        Rect {
+            x: x + (PLAYER_WIDTH - width) / 2.0,
+            y: MAX_Y - PLAYER_HEIGHT - BALL_RADIUS * 1.9,
+            w: width,
+            h: PLAYER_HEIGHT,
+        }
+    }
+
+    fn ball_start(x: f32) -> Vec2 {
+        vec2(x, MAX_Y - PLAYER_HEIGHT - BALL_RADIUS * 1.9 - BALL_RADIUS - PUSH_EPSILON)
+    }
+
+    pub fn box_rect(x: usize, y: usize) -> Rect {
+        Rect {
+            x: (x as f32) * BOX_WIDTH,
+            y: (y as f32) * BOX_HEIGHT,
+            w: BOX_WIDTH,
+            h: BOX_HEIGHT,
+        }
+    }
+
+    fn break_box(&mut self, bx: usize, by: usize) {
+        let brick = self.boxes[by][bx];
+
+        self.boxes[by][bx] = Brick::Empty;
+        self.score += BOX_SCORE;
+        self.broken += 1;
+        self.combo += 1;
+        self.drops.maybe_spawn(Self::box_rect(bx, by).center());
+
+        if brick != Brick::Explosive {
+            return;
+        }
+
+        for ny in by.saturating_sub(1)..(by + 2).min(BOX_LINE_COUNT) {
+            for nx in bx.saturating_sub(1)..(bx + 2).min(BOX_PER_LINE) {
+                if self.boxes[ny][nx].is_breakable() {
+                    self.break_box(nx, ny);
+                }
+            }
+        }
+    }
+
+    fn bounce_off_paddle(&mut self, new_ball_pos: &mut Vec2, rect: Rect, delta: f32) {
         // The player paddle is kind of special
         // 1. We pretend it is curved with the height function of -0.2 * 2.0 * x
         // 2. Player paddle always pushes the ball to the top of it
         // 3. The horizontal component of ball's velocity can be affected if the paddle
         //     was moving horizontally during impact
-        if Self::ball_in_rect(self.ball_pos, player_rect) {
+        if Self::ball_in_rect(self.ball_pos, rect) {
             /* df/dx */
             let d_height = |x: f32| {
                 -0.2 * 4.0 * x.powf(3.0)
@@ -135,7 +447,7 @@ impl Physics {
                 vec2(-t.y, -t.x)
             };
             let ball_x_on_surface = (
-                (self.ball_pos.x - player_rect.left()) / player_rect.w
+                (self.ball_pos.x - rect.left()) / rect.w
             ) * 2.0 - 1.0;
 
             let push_n = normal(ball_x_on_surface);
@@ -143,35 +455,46 @@ impl Physics {
             self.ball_dir += push_n;
             self.ball_dir = self.ball_dir.normalize();
 
-            if self.player_delta != 0.0 {
-                self.ball_dir.x += BALL_NUDGE * (self.player_delta / self.player_delta);
+            if delta != 0.0 {
+                self.ball_dir.x += BALL_NUDGE * (delta / delta);
             }
             self.ball_dir = self.ball_dir.normalize();
 
-            new_ball_pos.y = player_rect.y - BALL_RADIUS - PUSH_EPSILON;
+            new_ball_pos.y = rect.y - BALL_RADIUS - PUSH_EPSILON;
+            self.combo = 0;
         }
-
-        self.ball_pos = new_ball_pos;
-
-        false
     }
 
-    pub fn player_rect(&self) -> Rect {
-        Rect {
-            x: self.player_x,
-            y: MAX_Y - PLAYER_HEIGHT - BALL_RADIUS * 1.9,
-            w: PLAYER_WIDTH,
-            h: PLAYER_HEIGHT,
+    fn bounce_off_rect(&mut self, new_ball_pos: &mut Vec2, rect: Rect) {
+        if Self::ball_bumped_vertically(self.ball_pos, rect) {
+            self.ball_dir.y *= -1.0;
+            if self.ball_pos.y > rect.bottom() {
+                new_ball_pos.y = rect.bottom() + BALL_RADIUS + PUSH_EPSILON;
+            } else {
+                new_ball_pos.y = rect.top() - BALL_RADIUS - PUSH_EPSILON;
+            }
+        } else {
+            self.ball_dir.x *= -1.0;
+            if self.ball_pos.x > rect.right() {
+                new_ball_pos.x = rect.right() + BALL_RADIUS + PUSH_EPSILON;
+            } else {
+                new_ball_pos.x = rect.left() - BALL_RADIUS - PUSH_EPSILON;
+            }
         }
     }
 
-    pub fn box_rect(x: usize, y: usize) -> Rect {
-        Rect {
-            x: (x as f32) * BOX_WIDTH,
-            y: (y as f32) * BOX_HEIGHT,
-            w: BOX_WIDTH,
-            h: BOX_HEIGHT,
-        }
+    fn enemy_blocked(boxes: &[[Brick; BOX_PER_LINE]; BOX_LINE_COUNT], pos: Vec2) -> bool {
+        let enemy_rect = Rect {
+            x: pos.x - ENEMY_RADIUS,
+            y: pos.y - ENEMY_RADIUS,
+            w: ENEMY_RADIUS * 2.0,
+            h: ENEMY_RADIUS * 2.0,
+        };
+
+        (0..BOX_LINE_COUNT)
+            .flat_map(|by| (0..BOX_PER_LINE).map(move |bx| (bx, by)))
+            .filter(|&(bx, by)| boxes[by][bx] != Brick::Empty)
+            .any(|(bx, by)| Self::box_rect(bx, by).overlaps(&enemy_rect))
     }
 
     fn ball_bumped_vertically(pos: Vec2, rect: Rect) -> bool {
@@ -0,0 +1,234 @@
+use macroquad::prelude::*;
+use nanoserde::{DeJson, SerJson};
+
+use crate::game_model::GameModel;
+
+const VERTEX_PATH: &str = "assets/shaders/post.vert";
+/// How long the colours stay apart after a hit
+const HIT_FLASH_TIME: f32 = 0.3;
+/// How far apart the colours get at the edges right after a hit, in pixels
+const HIT_ABERRATION: f32 = 4.0;
+
+/// Which of the screen effects are on
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeJson, SerJson)]
+pub struct PostSettings {
+    /// Off draws the arena straight to the screen, which is the way to go on weak devices
+    #[nserde(default = "true")]
+    pub enabled: bool,
+    #[nserde(default)]
+    pub crt: bool,
+    #[nserde(default = "true")]
+    pub bloom: bool,
+    #[nserde(default = "true")]
+    pub aberration: bool,
+    #[nserde(default = "true")]
+    pub vignette: bool,
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            crt: false,
+            bloom: true,
+            aberration: true,
+            vignette: true,
+        }
+    }
+}
+
+/// The post-processing passes, in the order they get applied
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    Bloom,
+    Aberration,
+    Crt,
+    Vignette,
+}
+
+impl Pass {
+    pub const ALL: [Pass; 4] = [
+        Pass::Bloom,
+        Pass::Aberration,
+        Pass::Crt,
+        Pass::Vignette,
+    ];
+
+    fn fragment_path(self) -> &'static str {
+        match self {
+            Pass::Bloom => "assets/shaders/bloom.frag",
+            Pass::Aberration => "assets/shaders/aberration.frag",
+            Pass::Crt => "assets/shaders/crt.frag",
+            Pass::Vignette => "assets/shaders/vignette.frag",
+        }
+    }
+
+    fn is_enabled(self, settings: &PostSettings) -> bool {
+        match self {
+            Pass::Bloom => settings.bloom,
+            Pass::Aberration => settings.aberration,
+            Pass::Crt => settings.crt,
+            Pass::Vignette => settings.vignette,
+        }
+    }
+}
+
+/// Draws the arena into an offscreen target and puts it on the screen
+/// through a chain of shaders. Shaders that fail to load or compile get
+/// left out of the chain, and with no shaders left the arena goes
+/// straight to the screen like it would with the effects off.
+pub struct PostFx {
+    settings: PostSettings,
+    materials: Vec<(Pass, Material)>,
+    /// The arena, then two targets the passes take turns drawing into
+    targets: Option<[RenderTarget; 3]>,
+    hit_flash: f32,
+}
+
+impl PostFx {
+    pub async fn new() -> Self {
+        let mut materials = Vec::with_capacity(Pass::ALL.len());
+        match load_string(VERTEX_PATH).await {
+            Ok(vertex) => for pass in Pass::ALL {
+                match Self::load_pass(&vertex, pass).await {
+                    Ok(x) => materials.push((pass, x)),
+                    Err(e) => warn!("Leaving out the {pass:?} effect: {e}"),
+                }
+            },
+            Err(e) => warn!("Drawing without screen effects: {e}"),
+        }
+
+        Self {
+            settings: PostSettings::default(),
+            materials,
+            targets: None,
+            hit_flash: 0.0,
+        }
+    }
+
+    /// Effects with the same shaders, for drawing another viewport
+    pub fn share(&self) -> Self {
+        Self {
+            settings: self.settings,
+            materials: self.materials.clone(),
+            targets: None,
+            hit_flash: 0.0,
+        }
+    }
+
+    async fn load_pass(vertex: &str, pass: Pass) -> anyhow::Result<Material> {
+        let fragment = load_string(pass.fragment_path()).await?;
+        let material = load_material(
+            ShaderSource::Glsl {
+                vertex,
+                fragment: &fragment,
+            },
+            MaterialParams {
+                uniforms: vec![
+                    UniformDesc::new("TextureSize", UniformType::Float2),
+                    UniformDesc::new("Strength", UniformType::Float1),
+                ],
+                ..Default::default()
+            },
+        )?;
+
+        Ok(material)
+    }
+
+    pub fn set_settings(&mut self, settings: PostSettings) {
+        self.settings = settings;
+    }
+
+    /// Starts the effects for what happened in the frame
+    pub fn update(&mut self, model: &GameModel, dt: f32) {
+        self.hit_flash = (self.hit_flash - dt).max(0.0);
+
+        if model.paddle_struck() || model.boss_hit() || model.ball_lost() {
+            self.hit_flash = HIT_FLASH_TIME;
+        }
+    }
+
+    /// The passes that have something to do this frame
+    fn active_passes(&self) -> impl Iterator<Item = &(Pass, Material)> {
+        self.materials.iter().filter(|(pass, _)| {
+            self.settings.enabled && pass.is_enabled(&self.settings) &&
+            (*pass != Pass::Aberration || self.hit_flash > 0.0)
+        })
+    }
+
+    /// The target the arena should be drawn into, which is `None` when
+    /// it should go straight to the screen. `size` is in physical pixels.
+    pub fn begin(&mut self, size: Vec2) -> Option<RenderTarget> {
+        self.active_passes().next()?;
+
+        let (width, height) = (size.x.max(1.0) as u32, size.y.max(1.0) as u32);
+        let resized = self.targets.as_ref()
+            .map_or(true, |x| x[0].texture.width() as u32 != width || x[0].texture.height() as u32 != height);
+        if resized {
+            self.targets = Some([(); 3].map(|_| {
+                let target = render_target(width, height);
+                target.texture.set_filter(FilterMode::Linear);
+                target
+            }));
+        }
+
+        self.targets.as_ref().map(|x| x[0].clone())
+    }
+
+    /// Puts the arena on the screen through the passes. `rect` is
+    /// the part of the screen it goes to, in physical pixels.
+    pub fn finish(&self, rect: Rect) {
+        let Some(targets) = &self.targets else {
+            return;
+        };
+        let passes = self.active_passes().collect::<Vec<_>>();
+        let Some(((last_pass, last), rest)) = passes.split_last() else {
+            return;
+        };
+
+        let size = vec2(rect.w, rect.h);
+        let mut source = &targets[0];
+        for (idx, (pass, material)) in rest.iter().enumerate() {
+            let dest = &targets[1 + idx % 2];
+            let mut cam = pass_cam(size);
+            cam.render_target = Some(dest.clone());
+
+            self.draw_pass(*pass, material, &source.texture, &cam, size);
+            source = dest;
+        }
+
+        let mut cam = pass_cam(size);
+        cam.viewport = Some((rect.x as i32, rect.y as i32, rect.w as i32, rect.h as i32));
+        self.draw_pass(*last_pass, last, &source.texture, &cam, size);
+    }
+
+    fn draw_pass(&self, pass: Pass, material: &Material, texture: &Texture2D, cam: &Camera2D, size: Vec2) {
+        set_camera(cam);
+        material.set_uniform("TextureSize", size);
+        if pass == Pass::Aberration {
+            material.set_uniform("Strength", HIT_ABERRATION * self.hit_flash / HIT_FLASH_TIME);
+        }
+
+        gl_use_material(material);
+        draw_texture_ex(
+            texture,
+            0.0,
+            0.0,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(size),
+                ..Default::default()
+            },
+        );
+        gl_use_default_material();
+    }
+}
+
+/// Covers a target or a viewport of `size` pixels the same way up the arena is drawn
+fn pass_cam(size: Vec2) -> Camera2D {
+    Camera2D {
+        target: size / 2.0,
+        zoom: 2.0 / size,
+        ..Default::default()
+    }
+}
@@ -0,0 +1,93 @@
+use macroquad::prelude::*;
+use nanoserde::{DeJson, SerJson};
+
+use crate::{checksum::Checksum, physics::MAX_Y, rng::Rng};
+
+pub const MAX_DROPS: usize = 4;
+pub const DROP_CHANCE: f32 = 0.08;
+pub const DROP_SPEED: f32 = 70.0;
+pub const DROP_WIDTH: f32 = 24.0;
+pub const DROP_HEIGHT: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeJson, SerJson)]
+pub enum PowerUp {
+    /// Opens a gate in the right wall that leads to the next stage
+    Warp,
+}
+
+#[derive(Clone, Copy, Debug, DeJson, SerJson)]
+pub struct Drop {
+    #[nserde(proxy = "crate::save::SavedVec2")]
+    pub pos: Vec2,
+    pub kind: PowerUp,
+}
+
+impl Drop {
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: self.pos.x - DROP_WIDTH / 2.0,
+            y: self.pos.y - DROP_HEIGHT / 2.0,
+            w: DROP_WIDTH,
+            h: DROP_HEIGHT,
+        }
+    }
+}
+
+/// The power-ups that are falling down, waiting to be caught
+#[derive(Clone, Copy, Debug, DeJson, SerJson)]
+pub struct Drops {
+    pub slots: [Option<Drop>; MAX_DROPS],
+    rng: Rng,
+}
+
+impl Drops {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            slots: [None; MAX_DROPS],
+            rng: Rng::new(seed),
+        }
+    }
+
+    pub fn checksum(&self, sum: &mut Checksum) {
+        for slot in &self.slots {
+            sum.bool(slot.is_some());
+            if let Some(drop) = slot {
+                sum.vec2(drop.pos);
+                sum.u32(drop.kind as u32);
+            }
+        }
+        self.rng.checksum(sum);
+    }
+
+    /// Rolls the dice on dropping a power-up at `pos`
+    pub fn maybe_spawn(&mut self, pos: Vec2) {
+        if self.rng.next_f32() >= DROP_CHANCE {
+            return;
+        }
+
+        let Some(slot) = self.slots.iter_mut().find(|x| x.is_none()) else { return; };
+        *slot = Some(Drop {
+            pos,
+            kind: PowerUp::Warp,
+        });
+    }
+
+    /// Moves the drops down. Returns the power-up the paddle caught, if any.
+    pub fn update(&mut self, dt: f32, paddle: Rect) -> Option<PowerUp> {
+        let mut caught = None;
+
+        for slot in self.slots.iter_mut() {
+            let Some(drop) = slot else { continue; };
+            drop.pos.y += DROP_SPEED * dt;
+
+            if drop.rect().overlaps(&paddle) {
+                caught = Some(drop.kind);
+                *slot = None;
+            } else if drop.pos.y - DROP_HEIGHT > MAX_Y {
+                *slot = None;
+            }
+        }
+
+        caught
+    }
+}
@@ -1,169 +1,209 @@
+use std::rc::Rc;
+
 use macroquad::prelude::*;
 
-use crate::{game_model::GameModel, physics::{self, Physics, BALL_RADIUS, BOX_HEIGHT, BOX_LINE_COUNT, BOX_WIDTH, PLAYER_HEIGHT, PLAYER_WIDTH}, GameState};
-use macroquad_particles::{self as particles, BlendMode, ColorCurve, EmitterConfig};
+use crate::{
+    boss::{Boss, ProjectileKind, BOSS_HEALTH, PROJECTILE_RADIUS},
+    camera::CameraFx,
+    enemy::{self, Behaviour, Enemies, ENEMY_RADIUS},
+    game_model::GameModel,
+    particle_fx::{Effect, ParticleFx},
+    physics::{self, Brick, Physics, BALL_RADIUS, BOX_HEIGHT, BOX_LINE_COUNT, BOX_WIDTH, PLAYER_HEIGHT},
+    post::PostFx,
+    powerup::{Drops, PowerUp},
+    settings::Settings,
+    theme::{self, ColorVision, Palette, Theme, DEFAULT_THEME},
+    GameState,
+};
+use macroquad_particles as particles;
+use nanoserde::{DeJson, SerJson};
 
 const WALL_WIGGLE_TIME: f32 = 0.15;
 const WALL_PUSH: f32 = 2.0;
 const WALL_HOR_OFF: f32 = 4.0;
 const WALL_WIDTH: f32 = 16.0;
+const WARP_GATE_PAD: f32 = 6.0;
+const DROP_FONT_SIZE: u16 = 10;
 
-fn trail() -> particles::EmitterConfig {
-    particles::EmitterConfig {
-        emitting: true,
-        lifetime: 1.2,
-        lifetime_randomness: 0.7,
-        explosiveness: 0.01,
-        amount: 15,
-        initial_direction_spread: 0.4 * std::f32::consts::PI,
-        initial_velocity: 100.0,
-        size: 1.0,
-        gravity: vec2(0.0, 1000.0),
-        blend_mode: BlendMode::Alpha,
-        emission_shape: macroquad_particles::EmissionShape::Sphere { radius: BALL_RADIUS },
-        colors_curve: ColorCurve {
-            start: Color::from_hex(0xDDFBFF),
-            mid: BLANK,
-            end: BLANK,
-        },
-        ..Default::default()
-    }
+/// How many particles the effects spawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, DeJson, SerJson)]
+pub enum ParticleQuality {
+    Low,
+    Medium,
+    #[default]
+    High,
 }
 
-fn explosion() -> particles::EmitterConfig {
-    particles::EmitterConfig {
-        one_shot: true,
-        emitting: false,
-        lifetime: 0.3,
-        lifetime_randomness: 0.7,
-        explosiveness: 0.99,
-        amount: 30,
-        initial_direction_spread: 2.0 * std::f32::consts::PI,
-        initial_velocity: 200.0,
-        size: 1.5,
-        gravity: vec2(0.0, 1000.0),
-        blend_mode: BlendMode::Alpha,
-        emission_shape: macroquad_particles::EmissionShape::Rect {
-            width: BOX_WIDTH,
-            height: BOX_HEIGHT,
-        },
-        colors_curve: ColorCurve {
-            start: Color::from_hex(0x333354),
-            mid: Color::from_hex(0x333354),
-            end: BLACK,
-        },
-        ..Default::default()
+impl ParticleQuality {
+    /// The part of the full particle amount that gets emitted
+    pub fn amount_scale(self) -> f32 {
+        match self {
+            ParticleQuality::Low => 0.25,
+            ParticleQuality::Medium => 0.5,
+            ParticleQuality::High => 1.0,
+        }
     }
 }
 
-fn ball_explosion() -> particles::EmitterConfig {
-    particles::EmitterConfig {
-        one_shot: true,
-        emitting: false,
-        lifetime: 1.0,
-        lifetime_randomness: 0.7,
-        explosiveness: 0.99,
-        amount: 10,
-        initial_direction_spread: 2.0 * std::f32::consts::PI,
-        initial_velocity: 100.0,
-        size: 20.0,
-        gravity: vec2(0.0, -1000.0),
-        blend_mode: BlendMode::Alpha,
-        emission_shape: macroquad_particles::EmissionShape::Sphere { radius: BALL_RADIUS * 4.0 },
-        initial_angular_velocity: 5.0,
-        angular_accel: 0.0,
-        angular_damping: 0.01,
-        colors_curve: ColorCurve {
-            start: Color::from_hex(0xDDFBFF),
-            mid: Color { r: 1.0, g: 0.0, b: 0.0, a: 0.0, },
-            end: BLANK,
-        },
-        ..Default::default()
-    }
+/// The part of the screen an arena is drawn to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Viewport {
+    Full,
+    Left,
+    Right,
 }
 
 pub struct Render {
-    ball1: Texture2D,
-    ball2: Texture2D,
-    ball3: Texture2D,
-    pla1: Texture2D,
-    pla2: Texture2D,
-    pla3: Texture2D,
-    bricks: Texture2D,
-    outline: Texture2D,
-    wall: Texture2D,
+    /// The default theme comes first
+    themes: Rc<[Theme]>,
+    theme: usize,
+    particle_quality: ParticleQuality,
+    color_vision: ColorVision,
+    high_contrast: bool,
+    brick_patterns: bool,
     ball_emit: particles::Emitter,
     pl_emit: particles::Emitter,
-    brick_emit: particles::Emitter,
-    ball_exp: particles::Emitter,
-    last_brick_break: Vec2,
+    partner_emit: particles::Emitter,
+    bursts: ParticleFx,
     l_wall_wiggle: f32,
     r_wall_wiggle: f32,
+    viewport: Viewport,
+    camera: CameraFx,
+    post: PostFx,
 }
 
 impl Render {
     pub async fn new() -> anyhow::Result<Self> {
-        let sad = load_texture("assets/ded.png").await?;
-
-        Ok(Self {
-            /* */
-            ball1: load_texture("assets/ball1.png").await?,
-            ball2: load_texture("assets/ball2.png").await?,
-            ball3: load_texture("assets/ball3.png").await?,
-            /* */
-            pla1: load_texture("assets/pl1.png").await?,
-            pla2: load_texture("assets/pl2.png").await?,
-            pla3: load_texture("assets/pl3.png").await?,
-            /* */
-            bricks: load_texture("assets/bricks.png").await?,
-            outline: load_texture("assets/brick_outline.png").await?,
-            /* */
-            wall: load_texture("assets/wall.png").await?,
+        let themes = theme::load_themes().await?;
+
+        Ok(Self::with_assets(themes.into(), PostFx::new().await, Viewport::Full))
+    }
+
+    /// Another renderer for the same window, drawing into `viewport`. The
+    /// themes and shaders get shared instead of loaded a second time.
+    pub fn share(&self, viewport: Viewport) -> Self {
+        Self::with_assets(self.themes.clone(), self.post.share(), viewport)
+    }
+
+    fn with_assets(themes: Rc<[Theme]>, post: PostFx, viewport: Viewport) -> Self {
+        let emitter = |effect| particles::Emitter::new(themes[0].emitter(effect));
+
+        Self {
             l_wall_wiggle: 0.0,
             r_wall_wiggle: 0.0,
-            /* */
-            ball_emit: particles::Emitter::new(EmitterConfig {
-                texture: None,
-                ..trail()
-            }),
-            pl_emit: particles::Emitter::new(EmitterConfig {
-                texture: None,
-                ..trail()
-            }),
-            brick_emit:  particles::Emitter::new(EmitterConfig {
-                texture: None,
-                ..explosion()
-            }),
-            ball_exp:  particles::Emitter::new(EmitterConfig {
-                texture: Some(sad),
-                ..ball_explosion()
-            }),
-            last_brick_break: Vec2::ZERO,
-        })
+            ball_emit: emitter(Effect::Trail),
+            pl_emit: emitter(Effect::Trail),
+            partner_emit: emitter(Effect::Trail),
+            bursts: ParticleFx::new(Effect::ALL.map(|x| themes[0].emitter(x)).to_vec()),
+            themes,
+            theme: 0,
+            particle_quality: ParticleQuality::default(),
+            color_vision: ColorVision::default(),
+            high_contrast: false,
+            brick_patterns: false,
+            viewport,
+            camera: CameraFx::new(),
+            post,
+        }
     }
 
-    pub fn draw(&mut self, model: &GameModel) {
-        self.setup_cam();
+    pub fn set_viewport(&mut self, viewport: Viewport) {
+        self.viewport = viewport;
+    }
+
+    /// The ids of the themes, the default one first
+    pub fn theme_ids(&self) -> Vec<String> {
+        self.themes.iter().map(|x| x.id.clone()).collect()
+    }
 
-        clear_background(Color {
-            r: 0.0,
-            g: 0.0,
-            b: 0.02,
-            a: 1.0,
+    /// Switches to the theme with the id. Unknown ids get the default theme.
+    pub fn select_theme(&mut self, id: &str) {
+        let idx = self.themes.iter().position(|x| x.id == id).unwrap_or_else(|| {
+            if self.theme != 0 {
+                warn!("There is no theme {id:?}, using {DEFAULT_THEME}");
+            }
+            0
         });
 
-        draw_rectangle(
+        if idx != self.theme {
+            self.theme = idx;
+            self.rebuild_emitters();
+        }
+    }
+
+    /// Picks up the particle quality, the colours, reduced motion and the screen effects
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.set_particle_quality(settings.particle_quality);
+        self.color_vision = settings.color_vision;
+        self.high_contrast = settings.high_contrast;
+        self.brick_patterns = settings.brick_patterns;
+        self.camera.set_reduce_motion(settings.reduce_motion);
+        self.post.set_settings(settings.post);
+    }
+
+    /// Whether a freeze frame is going on
+    pub fn is_frozen(&self) -> bool {
+        self.camera.is_frozen()
+    }
+
+    fn set_particle_quality(&mut self, quality: ParticleQuality) {
+        if quality != self.particle_quality {
+            self.particle_quality = quality;
+            self.rebuild_emitters();
+        }
+    }
+
+    /// Sets the emitters up for the theme and the particle quality.
+    /// Emitters only ever get scaled down from the full amount of the theme.
+    fn rebuild_emitters(&mut self) {
+        let theme = &self.themes[self.theme];
+        let quality = self.particle_quality;
+        let config = |effect| {
+            let mut config = theme.emitter(effect);
+            config.amount = ((config.amount as f32 * quality.amount_scale()) as u32).max(1);
+            config
+        };
+
+        self.ball_emit = particles::Emitter::new(config(Effect::Trail));
+        self.pl_emit = particles::Emitter::new(config(Effect::Trail));
+        self.partner_emit = particles::Emitter::new(config(Effect::Trail));
+        self.bursts.set_configs(Effect::ALL.map(config).to_vec());
+    }
+
+    pub fn draw(&mut self, model: &GameModel) {
+        self.camera.update(model, get_frame_time());
+        self.post.update(model, get_frame_time());
+        let rect = Self::viewport_rect(self.viewport);
+        let target = self.post.begin(rect.size());
+        let offscreen = target.is_some();
+        self.setup_cam(target);
+        let palette = self.palette();
+
+        // Clearing ignores the viewport, so only the first arena of the frame does it
+        if offscreen || self.viewport != Viewport::Right {
+            clear_background(palette.background);
+        }
+
+        let arena = Rect::new(
             -WALL_WIDTH + WALL_HOR_OFF,
             0.0,
             physics::MAX_X + (WALL_WIDTH - WALL_HOR_OFF) * 2.0,
             physics::MAX_Y,
-            Color {
-                r: 0.0,
-                g: 0.0,
-                b: 0.12,
-                a: 1.0,
-            }
         );
+        draw_rectangle(arena.x, arena.y, arena.w, arena.h, palette.arena);
+        // High contrast keeps the arena plain black
+        if let Some(background) = self.theme().background.as_ref().filter(|_| !self.high_contrast) {
+            draw_texture_ex(
+                background,
+                arena.x,
+                arena.y,
+                WHITE,
+                DrawTextureParams {
+                    dest_size: Some(arena.size()),
+                    ..Default::default()
+                },
+            );
+        }
 
         if model.ball_bounced_off_left_wall() {
             self.l_wall_wiggle = WALL_WIGGLE_TIME;
@@ -183,7 +223,7 @@ impl Render {
             -WALL_WIDTH + WALL_HOR_OFF
         };
         draw_texture_ex(
-            &self.wall,
+            &self.theme().wall,
             l_wall_x,
             wall_y,
             WHITE,
@@ -197,7 +237,7 @@ impl Render {
             physics::MAX_X - WALL_HOR_OFF
         };
         draw_texture_ex(
-            &self.wall,
+            &self.theme().wall,
             r_wall_x,
             wall_y,
             WHITE,
@@ -206,32 +246,119 @@ impl Render {
                 ..Default::default()
             },
         );
+        if model.physics.warp_timer > 0.0 {
+            let gate_y = model.physics.player_rect().y - WARP_GATE_PAD;
+            let pulse = ((get_time() as f32) * 8.0).sin() * 0.5 + 0.5;
+
+            draw_rectangle(
+                r_wall_x,
+                gate_y,
+                WALL_WIDTH,
+                physics::MAX_Y - gate_y,
+                palette.arena,
+            );
+            draw_rectangle_lines(
+                r_wall_x,
+                gate_y,
+                WALL_WIDTH,
+                physics::MAX_Y - gate_y,
+                2.0,
+                palette.cool.with_alpha(0.5 + pulse * 0.5),
+            );
+        }
 
+        self.draw_gates(&model.physics.enemies);
+        if model.state == GameState::Editor {
+            self.draw_grid();
+        }
         self.draw_blocks(&model.physics);
+        if let Some(boss) = &model.physics.boss {
+            self.draw_boss(boss);
+        }
+        self.draw_enemies(&model.physics.enemies);
+        self.draw_drops(&model.physics.drops);
         self.draw_player(&model.physics);
 
-        if matches!(model.state, GameState::Active | GameState::Paused) {
+        if matches!(model.state, GameState::Active | GameState::Paused | GameState::Start) {
             self.draw_ball(&model.physics);
         }
 
-        if let Some((bx, by)) = model.broken_box() {
-            self.brick_emit.config.emitting = true;
-            self.last_brick_break = vec2(
+        for (bx, by) in model.broken_boxes() {
+            self.bursts.spawn(Effect::Explosion, vec2(
                 BOX_WIDTH * (bx as f32 + 0.5),
                 BOX_HEIGHT * (by as f32 + 0.6),
-            );
+            ));
+        }
+
+        for pos in model.killed_enemies() {
+            self.bursts.spawn(Effect::EnemyExplosion, pos);
+        }
+
+        if let Some(boss) = model.physics.boss.filter(|_| model.boss_defeated()) {
+            self.bursts.spawn(Effect::BossExplosion, boss.rect().center());
+        }
+
+        if model.paddle_struck() {
+            self.bursts.spawn(Effect::PaddleSpark, model.physics.player_rect().center());
         }
 
-        if model.gameover_just_happened(){
-            self.ball_exp.config.emitting = true;
+        if model.gameover_just_happened() {
+            self.bursts.spawn(Effect::BallExplosion, model.physics.ball_pos);
         }
 
-        self.ball_exp.draw(model.physics.ball_pos);
-        self.brick_emit.draw(self.last_brick_break);
+        self.bursts.draw(get_frame_time());
+
+        if offscreen {
+            self.post.finish(rect);
+        }
+    }
+
+    fn theme(&self) -> &Theme {
+        &self.themes[self.theme]
+    }
+
+    /// The palette of the theme, adapted to the colour settings
+    pub fn palette(&self) -> Palette {
+        self.theme().palette.adapted(self.color_vision, self.high_contrast)
+    }
+
+    pub fn brick_patterns(&self) -> bool {
+        self.brick_patterns
+    }
+
+    /// Targets get the arena whole, the viewport only matters once it goes on the screen
+    fn setup_cam(&mut self, target: Option<RenderTarget>) {
+        let mut cam = Self::viewport_cam(self.viewport);
+        self.camera.apply(&mut cam);
+        if target.is_some() {
+            cam.viewport = None;
+            cam.render_target = target;
+        }
+
+        set_camera(&cam);
+    }
+
+    /// The camera that the arena is drawn with
+    pub fn arena_cam() -> Camera2D {
+        Self::viewport_cam(Viewport::Full)
+    }
+
+    /// The part of the screen the viewport covers. Camera viewports are in physical pixels.
+    fn viewport_rect(viewport: Viewport) -> Rect {
+        let width = screen_width() * screen_dpi_scale();
+        let height = screen_height() * screen_dpi_scale();
+
+        match viewport {
+            Viewport::Full => Rect::new(0.0, 0.0, width, height),
+            Viewport::Left => Rect::new(0.0, 0.0, width / 2.0, height),
+            Viewport::Right => Rect::new(width / 2.0, 0.0, width / 2.0, height),
+        }
     }
 
-    fn setup_cam(&mut self) {
-        let view_width = (screen_width() / screen_height()) * physics::MAX_Y;
+    fn viewport_cam(viewport: Viewport) -> Camera2D {
+        let Rect { x, w, h: height, .. } = Self::viewport_rect(viewport);
+
+        let view_width = (w / height) * physics::MAX_Y;
         let mut cam = Camera2D::from_display_rect(Rect {
             x: -(view_width - physics::MAX_X) / 2.0,
             y: 0.0,
@@ -239,14 +366,16 @@ impl Render {
             h: physics::MAX_Y,
         });
         cam.zoom.y *= -1.0;
+        if viewport != Viewport::Full {
+            cam.viewport = Some((x as i32, 0, w as i32, height as i32));
+        }
 
-        set_camera(&cam);
+        cam
     }
 
     fn draw_ball(&mut self, phys: &Physics) {
-        let t = get_time() as f32;
-        let tex = [&self.ball1, &self.ball2, &self.ball3];
-        let tex = tex[(t * 5.0) as usize % 3];
+        let theme = &self.themes[self.theme];
+        let tex = theme::frame(&theme.ball, theme.ball_fps);
         draw_texture_ex(
             tex,
             phys.ball_pos.x - BALL_RADIUS,
@@ -270,16 +399,28 @@ impl Render {
     }
 
     fn draw_player(&mut self, phys: &Physics) {
-        let t = get_time() as f32;
-        let rect = phys.player_rect();
+        self.draw_paddle(phys, phys.player_rect(), phys.player_delta, false);
+        if let Some(rect) = phys.partner_rect() {
+            self.draw_paddle(phys, rect, phys.partner_delta, true);
+        }
+    }
 
-        let tex = [&self.pla1, &self.pla2, &self.pla3];
-        let tex = tex[(t * 5.0) as usize % 3];
+    fn draw_paddle(&mut self, phys: &Physics, rect: Rect, delta: f32, partner: bool) {
+        let theme = &self.themes[self.theme];
+        let tex = theme::frame(&theme.paddle, theme.paddle_fps);
+        // The second player's paddle is tinted, so that nobody mixes them up
+        let col = if phys.stun_timer > 0.0 {
+            self.palette().warm
+        } else if partner {
+            self.palette().cool
+        } else {
+            WHITE
+        };
         draw_texture_ex(
             tex,
             rect.x,
             rect.y,
-            WHITE,
+            col,
             DrawTextureParams {
                 dest_size: Some(vec2(
                     rect.w,
@@ -292,41 +433,188 @@ impl Render {
                 pivot: None,
             },
         );
-        if phys.player_delta == 0.0 {
-            self.pl_emit.config.emitting = false;
+
+        let emit = if partner { &mut self.partner_emit } else { &mut self.pl_emit };
+        if delta == 0.0 {
+            emit.config.emitting = false;
         } else {
-            self.pl_emit.config.emitting = true;
-            self.pl_emit.config.initial_direction = -vec2(phys.player_delta, 0.0).normalize();
-            self.pl_emit.config.gravity = vec2(phys.player_delta, 0.0).normalize();
+            emit.config.emitting = true;
+            emit.config.initial_direction = -vec2(delta, 0.0).normalize();
+            emit.config.gravity = vec2(delta, 0.0).normalize();
         }
 
-        self.pl_emit.draw(vec2(
-            rect.x + PLAYER_WIDTH / 2.0,
+        emit.draw(vec2(
+            rect.x + rect.w / 2.0,
             rect.y + PLAYER_HEIGHT
-        ) - vec2(phys.player_delta, 0.0).normalize_or_zero() * PLAYER_WIDTH / 2.0);
+        ) - vec2(delta, 0.0).normalize_or_zero() * rect.w / 2.0);
+    }
+
+    fn draw_gates(&self, enemies: &Enemies) {
+        for (idx, open) in enemies.gates.iter().enumerate() {
+            let rect = Enemies::gate_rect(idx);
+            // The shutter slides up while the gate is open
+            let shutter = 1.0 - (open / enemy::GATE_OPEN_TIME * 2.0).min(1.0);
+            let palette = self.palette();
+
+            draw_rectangle(
+                rect.x - 2.0,
+                rect.y,
+                rect.w + 4.0,
+                rect.h,
+                palette.dark,
+            );
+            draw_rectangle(
+                rect.x,
+                rect.y,
+                rect.w,
+                rect.h * shutter,
+                palette.light,
+            );
+        }
+    }
+
+    fn draw_boss(&self, boss: &Boss) {
+        let theme = self.theme();
+        for proj in boss.projectiles.iter().flatten() {
+            let col = match proj.kind {
+                ProjectileKind::Shrink => self.palette().hot,
+                ProjectileKind::Stun => self.palette().warm,
+            };
+
+            draw_circle(proj.pos.x, proj.pos.y, PROJECTILE_RADIUS, col);
+        }
+
+        if !boss.is_alive() {
+            return;
+        }
+
+        let rect = boss.rect();
+        let t = get_time() as f32;
+        let idx = (t * theme.boss_fps) as usize % 16;
+        let (tx, ty) = (idx % 4, idx / 4);
+
+        draw_texture_ex(&theme.outline,
+            rect.x - 4.0,
+            rect.y - 4.0,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(vec2(rect.w + 8.0, rect.h + 8.0)),
+                ..Default::default()
+            },
+        );
+        draw_texture_ex(&theme.bricks,
+            rect.x,
+            rect.y,
+            self.palette().hot,
+            DrawTextureParams {
+                dest_size: Some(vec2(rect.w, rect.h)),
+                source: Some(Rect {
+                    x: (tx as f32) * 32.0,
+                    y: (ty as f32) * 16.0,
+                    w: 32.0,
+                    h: 16.0,
+                }),
+                ..Default::default()
+            },
+        );
+
+        let health = boss.health as f32 / BOSS_HEALTH as f32;
+        draw_rectangle(rect.x, rect.y - 10.0, rect.w, 4.0, self.palette().dark);
+        draw_rectangle(rect.x, rect.y - 10.0, rect.w * health, 4.0, self.palette().light);
+    }
+
+    fn draw_drops(&self, drops: &Drops) {
+        let palette = self.palette();
+        for drop in drops.slots.iter().flatten() {
+            let rect = drop.rect();
+            let (col, letter) = match drop.kind {
+                PowerUp::Warp => (palette.cool, "W"),
+            };
+
+            draw_rectangle(rect.x, rect.y, rect.w, rect.h, col);
+            draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, palette.light);
+            let center = get_text_center(letter, None, DROP_FONT_SIZE, 1.0, 0.0);
+            draw_text(
+                letter,
+                rect.center().x - center.x,
+                rect.center().y - center.y,
+                DROP_FONT_SIZE as f32,
+                palette.dark,
+            );
+        }
+    }
+
+    fn draw_enemies(&self, enemies: &Enemies) {
+        let palette = self.palette();
+        for enemy in enemies.slots.iter().flatten() {
+            let col = match enemy.behaviour {
+                Behaviour::Drift => palette.cool,
+                Behaviour::Wander => palette.warm,
+                Behaviour::Chase => palette.hot,
+            };
+            let rotation = enemy.age * 90.0;
+
+            draw_poly(enemy.pos.x, enemy.pos.y, 6, ENEMY_RADIUS, rotation, col);
+            draw_poly_lines(
+                enemy.pos.x,
+                enemy.pos.y,
+                6,
+                ENEMY_RADIUS,
+                rotation,
+                1.0,
+                palette.light,
+            );
+        }
+    }
+
+    fn draw_grid(&self) {
+        for by in 0..physics::BOX_LINE_COUNT {
+            for bx in 0..physics::BOX_PER_LINE {
+                let box_rect = Physics::box_rect(bx, by);
+
+                draw_rectangle_lines(
+                    box_rect.x,
+                    box_rect.y,
+                    box_rect.w,
+                    box_rect.h,
+                    1.0,
+                    self.palette().dark,
+                );
+            }
+        }
     }
 
-    fn draw_blocks(&mut self, phys: &Physics) {
+    fn draw_blocks(&self, phys: &Physics) {
+        let theme = self.theme();
+        let palette = self.palette();
         for by in 0..physics::BOX_LINE_COUNT {
             for bx in 0..physics::BOX_PER_LINE {
-                if !phys.boxes[by][bx] {
+                let brick = phys.boxes[by][bx];
+                if brick == Brick::Empty {
                     continue;
                 }
 
                 let box_rect = Physics::box_rect(bx, by);
                 let mut idx = ((53 + bx) * 53 + by) % 16;
-                idx = (idx + (get_time() / 1.0) as usize) % 16;
+                idx = (idx + (get_time() as f32 * theme.brick_fps) as usize) % 16;
                 let tx = idx % 4;
                 let ty = idx / 4;
 
+                // Lower rows are brighter. High contrast keeps them all at full brightness.
+                let shade = if self.high_contrast {
+                    1.0
+                } else {
+                    (by as f32) / (BOX_LINE_COUNT as f32) * 0.5 + 0.5
+                };
+                let tint = palette.brick_tint(brick);
                 let brick_col = Color {
-                    r: (by as f32) / (BOX_LINE_COUNT as f32) * 0.5 + 0.5,
-                    g: (by as f32) / (BOX_LINE_COUNT as f32) * 0.5 + 0.5,
-                    b: (by as f32) / (BOX_LINE_COUNT as f32) * 0.5 + 0.5,
-                    a: 1.0,
+                    r: tint.r * shade,
+                    g: tint.g * shade,
+                    b: tint.b * shade,
+                    a: tint.a,
                 };
 
-                draw_texture_ex(&self.outline,
+                draw_texture_ex(&theme.outline,
                     box_rect.x - 2.0,
                     box_rect.y - 2.0,
                     brick_col,
@@ -339,7 +627,7 @@ impl Render {
                         pivot: None,
                     },
                 );
-                draw_texture_ex(&self.bricks,
+                draw_texture_ex(&theme.bricks,
                     box_rect.x,
                     box_rect.y,
                     brick_col,
@@ -357,7 +645,46 @@ impl Render {
                         pivot: None,
                     },
                 );
+
+                if self.brick_patterns {
+                    draw_brick_pattern(box_rect, brick, palette.background);
+                }
             }
         }
     }
+}
+
+/// Marks the brick type with a shape, so that it doesn't depend on the colour alone
+pub fn draw_brick_pattern(rect: Rect, brick: Brick, color: Color) {
+    let inner = Rect::new(rect.x + 3.0, rect.y + 3.0, rect.w - 6.0, rect.h - 6.0);
+    let center = rect.center();
+
+    match brick {
+        Brick::Empty | Brick::Normal => (),
+        // Two bars for a brick that takes two hits
+        Brick::Tough => for x in [center.x - 3.0, center.x + 3.0] {
+            draw_line(x, inner.top(), x, inner.bottom(), 2.0, color);
+        },
+        // A zigzag crack across
+        Brick::Cracked => {
+            let step = inner.w / 4.0;
+            for idx in 0..4 {
+                let (x0, x1) = (inner.x + step * idx as f32, inner.x + step * (idx + 1) as f32);
+                let (y0, y1) = if idx % 2 == 0 {
+                    (inner.top(), inner.bottom())
+                } else {
+                    (inner.bottom(), inner.top())
+                };
+                draw_line(x0, y0, x1, y1, 1.5, color);
+            }
+        },
+        // A frame, nothing gets through
+        Brick::Solid => draw_rectangle_lines(inner.x, inner.y, inner.w, inner.h, 2.0, color),
+        // A fuse mark in the middle
+        Brick::Explosive => {
+            draw_circle(center.x, center.y, 3.0, color);
+            draw_line(center.x - 6.0, inner.top(), center.x + 6.0, inner.bottom(), 1.5, color);
+            draw_line(center.x - 6.0, inner.bottom(), center.x + 6.0, inner.top(), 1.5, color);
+        },
+    }
 }
\ No newline at end of file
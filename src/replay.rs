@@ -0,0 +1,249 @@
+use anyhow::{bail, Context};
+use nanoserde::{DeJson, SerJson};
+
+use crate::{
+    game_model::player_won,
+    input::{ActionFrame, InputSource, InputView},
+    level::{self, Level},
+    mode::{format_time, GameMode, Run, Verdict},
+    sys,
+};
+
+/// The last finished run always gets recorded under this key
+pub const REPLAY_KEY: &str = "last_replay";
+
+#[derive(Clone, Copy, Debug, DeJson, SerJson)]
+pub struct ReplayEntry {
+    pub dt: f32,
+    pub frame: ActionFrame,
+}
+
+/// The input of every simulated frame of a run. Together with the mode
+/// and the seed it is enough to play the whole run again.
+#[derive(Clone, Debug, Default, DeJson, SerJson)]
+pub struct ReplayFile {
+    pub mode: String,
+    pub seed: u64,
+    pub entries: Vec<ReplayEntry>,
+}
+
+/// Writes down the frames of a run as it gets played
+pub struct Recorder {
+    file: ReplayFile,
+}
+
+impl Recorder {
+    pub fn new(run: &Run) -> Self {
+        Self {
+            file: ReplayFile {
+                mode: run.mode.label().to_owned(),
+                seed: run.seed,
+                entries: Vec::new(),
+            },
+        }
+    }
+
+    pub fn record(&mut self, dt: f32, frame: ActionFrame) {
+        self.file.entries.push(ReplayEntry { dt, frame });
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        sys::storage_store(REPLAY_KEY, &self.file.serialize_json())
+    }
+}
+
+/// Plays the recorded frames back, one per poll
+pub struct Replay {
+    file: ReplayFile,
+    pos: usize,
+}
+
+impl Replay {
+    pub fn new(file: ReplayFile) -> Self {
+        Self { file, pos: 0 }
+    }
+
+    /// How long the next frame lasted, `None` once the recording is over
+    pub fn dt(&self) -> Option<f32> {
+        self.file.entries.get(self.pos).map(|x| x.dt)
+    }
+}
+
+impl InputSource for Replay {
+    fn poll(&mut self, _view: &InputView) -> ActionFrame {
+        let Some(entry) = self.file.entries.get(self.pos) else {
+            return ActionFrame::default();
+        };
+        self.pos += 1;
+
+        entry.frame
+    }
+}
+
+/// Plays a recorded run without a window and prints how it ended
+pub fn verify(path: &str) -> anyhow::Result<()> {
+    let src = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {path}"))?;
+    let file: ReplayFile = DeJson::deserialize_json(&src)?;
+    let levels = level::read_levels()?;
+    let (run, score) = play(file, &levels)?;
+
+    println!(
+        "{} run: stage {}, score {}, time {}, {}",
+        run.mode.label(),
+        run.stage + 1,
+        score,
+        format_time(run.time),
+        if run.over { "over" } else { "unfinished" },
+    );
+
+    Ok(())
+}
+
+/// Simulates every recorded frame, returning where the run got to and the score
+fn play(file: ReplayFile, levels: &[Level]) -> anyhow::Result<(Run, u32)> {
+    let Some(mode) = GameMode::ALL.into_iter().find(|x| x.label() == file.mode) else {
+        bail!("Unknown mode {:?}", file.mode);
+    };
+    if mode == GameMode::Versus {
+        bail!("Versus replays aren't supported yet");
+    }
+
+    let mut run = Run::new(mode, file.seed);
+    let mut phys = run.stage_physics(levels);
+    let mut replay = Replay::new(file);
+
+    while let Some(dt) = replay.dt() {
+        let frame = replay.poll(&InputView {
+            phys: &phys,
+            dt,
+            two_players: mode.is_two_player(),
+        });
+
+        phys.new_frame();
+        frame.player.apply(&mut phys, dt);
+        frame.partner.apply_partner(&mut phys, dt);
+        let hit_floor = phys.update(dt);
+        run.tick(dt);
+
+        let cleared = player_won(&phys) || phys.warped;
+        match run.judge(cleared, hit_floor, levels.len()) {
+            Verdict::Playing => (),
+            Verdict::Respawn => phys.respawn_ball(),
+            Verdict::Advance | Verdict::Cleared => {
+                let score = phys.score;
+
+                run.next_stage(levels.len());
+                phys = run.stage_physics(levels);
+                if run.stage != 0 {
+                    phys.score = score;
+                }
+            },
+            Verdict::Finished | Verdict::Lost => break,
+        }
+    }
+
+    Ok((run, phys.score))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::PaddleInput;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    /// A minute of sweeping the paddle from wall to wall
+    fn record(mode: GameMode, seed: u64) -> ReplayFile {
+        let mut recorder = Recorder::new(&Run::new(mode, seed));
+        for idx in 0..3600 {
+            let right = idx / 90 % 2 == 0;
+            let player = PaddleInput { left: !right, right };
+            recorder.record(DT, ActionFrame {
+                player,
+                partner: player,
+                ..Default::default()
+            });
+        }
+
+        recorder.file
+    }
+
+    fn outcome((run, score): (Run, u32)) -> (usize, Option<u32>, u32, u32, bool) {
+        (run.stage, run.lives, run.time.to_bits(), score, run.over)
+    }
+
+    #[test]
+    fn frames_steer_the_paddles() {
+        let levels = level::read_levels().unwrap();
+        let mut phys = Run::new(GameMode::Coop, 5).stage_physics(&levels);
+        let start = (phys.player_x, phys.partner_x);
+        let mut replay = Replay::new(ReplayFile {
+            entries: vec![ReplayEntry {
+                dt: DT,
+                frame: ActionFrame {
+                    player: PaddleInput { left: true, right: false },
+                    partner: PaddleInput { left: false, right: true },
+                    ..Default::default()
+                },
+            }; 10],
+            ..Default::default()
+        });
+
+        while let Some(dt) = replay.dt() {
+            let frame = replay.poll(&InputView { phys: &phys, dt, two_players: true });
+            phys.new_frame();
+            frame.player.apply(&mut phys, dt);
+            frame.partner.apply_partner(&mut phys, dt);
+            phys.update(dt);
+        }
+
+        assert!(phys.player_x < start.0);
+        assert!(phys.partner_x > start.1);
+        assert_eq!(replay.poll(&InputView { phys: &phys, dt: DT, two_players: true }), ActionFrame::default());
+    }
+
+    #[test]
+    fn replays_are_deterministic() {
+        let levels = level::read_levels().unwrap();
+
+        for mode in [GameMode::Classic, GameMode::Endless, GameMode::Coop] {
+            let file = record(mode, 42);
+            let first = outcome(play(file.clone(), &levels).unwrap());
+            let second = outcome(play(file, &levels).unwrap());
+            assert_eq!(first, second, "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn replays_survive_saving() {
+        let levels = level::read_levels().unwrap();
+        let file = record(GameMode::Endless, 7);
+        let saved: ReplayFile = DeJson::deserialize_json(&file.serialize_json()).unwrap();
+
+        assert_eq!(
+            outcome(play(file, &levels).unwrap()),
+            outcome(play(saved, &levels).unwrap()),
+        );
+    }
+
+    #[test]
+    fn verify_accepts_a_recording() {
+        let path = std::env::temp_dir().join("quad-arcanoid-test-replay.json");
+        std::fs::write(&path, record(GameMode::Classic, 3).serialize_json()).unwrap();
+
+        let res = verify(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        res.unwrap();
+    }
+
+    #[test]
+    fn verify_rejects_versus_and_unknown_modes() {
+        let levels = level::read_levels().unwrap();
+
+        assert!(play(record(GameMode::Versus, 1), &levels).is_err());
+        let mut file = record(GameMode::Classic, 1);
+        file.mode = "Golf".to_owned();
+        assert!(play(file, &levels).is_err());
+    }
+}
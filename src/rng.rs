@@ -0,0 +1,49 @@
+use nanoserde::{DeJson, SerJson};
+
+use crate::checksum::Checksum;
+
+/// A tiny xorshift generator. Unlike `macroquad::rand` it is a plain value,
+/// so copying the simulation state copies its random stream as well.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, DeJson, SerJson)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Xorshift gets stuck on zero
+        Self { state: seed ^ 0x9E37_79B9_7F4A_7C15 | 1 }
+    }
+
+    pub fn checksum(&self, sum: &mut Checksum) {
+        sum.u64(self.state);
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+
+        (x >> 32) as u32
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        (self.next_u32() as u64) << 32 | self.next_u32() as u64
+    }
+
+    /// A number in `[0; 1)`
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    pub fn range(&mut self, lo: f32, hi: f32) -> f32 {
+        lo + (hi - lo) * self.next_f32()
+    }
+
+    /// A number in `[0; n)`
+    pub fn below(&mut self, n: u32) -> u32 {
+        self.next_u32() % n.max(1)
+    }
+}
@@ -0,0 +1,159 @@
+use std::sync::Mutex;
+
+use anyhow::bail;
+use macroquad::prelude::*;
+use nanoserde::{DeJson, SerJson};
+
+use crate::{mode::Run, physics::Physics, sys};
+
+pub const SAVE_KEY: &str = "saved_run";
+/// Bump this whenever the save format changes and teach [SaveFile::load]
+/// what to do with the old one
+pub const SAVE_VERSION: u32 = 1;
+
+/// The run that goes into storage the next time it gets flushed. The page
+/// may get closed between two frames, so it is kept where the visibility
+/// handler can reach it.
+static STAGED: Mutex<Option<SaveFile>> = Mutex::new(None);
+
+/// A run in progress, along with everything needed to pick it up again
+#[derive(Clone, Debug, DeJson, SerJson)]
+pub struct SaveFile {
+    pub version: u32,
+    pub run: Run,
+    pub physics: Physics,
+    /// Whether it is the scored daily attempt
+    pub daily_scored: bool,
+}
+
+/// The part every save format version has
+#[derive(DeJson)]
+struct SaveHeader {
+    version: u32,
+}
+
+impl SaveFile {
+    pub fn new(run: &Run, physics: Physics, daily_scored: bool) -> Self {
+        Self {
+            version: SAVE_VERSION,
+            run: run.clone(),
+            physics,
+            daily_scored,
+        }
+    }
+
+    pub fn load() -> anyhow::Result<Option<Self>> {
+        let Some(data) = sys::storage_load(SAVE_KEY) else {
+            return Ok(None);
+        };
+
+        Self::parse(&data).map(Some)
+    }
+
+    fn parse(data: &str) -> anyhow::Result<Self> {
+        let header: SaveHeader = DeJson::deserialize_json(data)?;
+
+        match header.version {
+            SAVE_VERSION => Ok(DeJson::deserialize_json(data)?),
+            x if x > SAVE_VERSION => bail!("The save is from a newer version of the game ({x})"),
+            // Nothing older has been released yet. Migrations go here once it has.
+            x => bail!("Save format {x} is no longer supported"),
+        }
+    }
+}
+
+/// Vec2 the way it is written into saves
+#[derive(Clone, Copy, Debug, DeJson, SerJson)]
+pub struct SavedVec2 {
+    x: f32,
+    y: f32,
+}
+
+impl From<&Vec2> for SavedVec2 {
+    fn from(v: &Vec2) -> Self {
+        Self { x: v.x, y: v.y }
+    }
+}
+
+impl From<&SavedVec2> for Vec2 {
+    fn from(v: &SavedVec2) -> Self {
+        vec2(v.x, v.y)
+    }
+}
+
+/// Sets what the next [flush] writes down. `None` means there is no run to continue.
+pub fn stage(file: Option<SaveFile>) {
+    *STAGED.lock().unwrap() = file;
+}
+
+pub fn staged() -> Option<SaveFile> {
+    STAGED.lock().unwrap().clone()
+}
+
+/// Writes the staged run into storage, or removes the save if there is none
+pub fn flush() -> anyhow::Result<()> {
+    match &*STAGED.lock().unwrap() {
+        Some(file) => sys::storage_store(SAVE_KEY, &file.serialize_json()),
+        None => sys::storage_remove(SAVE_KEY),
+    }
+}
+
+/// Called by the page once it gets hidden, as the tab might never come back
+#[cfg(target_family = "wasm")]
+#[no_mangle]
+pub extern "C" fn app_visibility_lost() {
+    if let Err(e) = flush() {
+        warn!("Failed to save the run: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{level::Level, mode::GameMode};
+
+    fn file() -> SaveFile {
+        let run = Run::new(GameMode::Endless, 11);
+        let physics = Physics::from_level(&Level::empty("test.lvl".to_owned()), 11);
+
+        SaveFile::new(&run, physics, false)
+    }
+
+    fn with_version(version: u32) -> String {
+        file().serialize_json().replacen(
+            &format!("\"version\":{SAVE_VERSION}"),
+            &format!("\"version\":{version}"),
+            1,
+        )
+    }
+
+    #[test]
+    fn current_version_loads() {
+        let saved = file();
+        let loaded = SaveFile::parse(&saved.serialize_json()).unwrap();
+
+        assert_eq!(loaded.version, SAVE_VERSION);
+        assert_eq!(loaded.run.seed, saved.run.seed);
+        assert_eq!(loaded.physics.ball_pos, saved.physics.ball_pos);
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let e = SaveFile::parse(&with_version(SAVE_VERSION + 1)).unwrap_err();
+
+        assert!(e.to_string().contains("newer version"), "{e}");
+    }
+
+    #[test]
+    fn older_version_is_rejected() {
+        let e = SaveFile::parse(&with_version(SAVE_VERSION - 1)).unwrap_err();
+
+        assert!(e.to_string().contains("no longer supported"), "{e}");
+    }
+
+    #[test]
+    fn broken_save_is_rejected() {
+        assert!(SaveFile::parse("{}").is_err());
+        assert!(SaveFile::parse(&file().serialize_json()[..40]).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+// nanoserde's derive expands `Option` fields into an `if let` that clippy wants as `?`.
+// The structs that nanoserde reads and have such fields live here, so that the allow
+// covers their derived code and nothing else. Their methods stay in their own modules.
+#![allow(clippy::question_mark)]
+
+use macroquad::prelude::*;
+use nanoserde::{DeJson, SerJson};
+
+use crate::{
+    boss::Boss,
+    enemy::Enemies,
+    mode::GameMode,
+    physics::{Brick, BOX_LINE_COUNT, BOX_PER_LINE},
+    powerup::Drops,
+    rng::Rng,
+};
+
+#[derive(Clone, Copy, Debug, DeJson, SerJson)]
+pub struct Physics {
+    pub player_x: f32,
+    pub player_delta: f32,
+    /// The second paddle in co-op. It always stays to the right of the first one.
+    pub partner_x: Option<f32>,
+    pub partner_delta: f32,
+    #[nserde(proxy = "crate::save::SavedVec2")]
+    pub ball_pos: Vec2,
+    #[nserde(proxy = "crate::save::SavedVec2")]
+    pub ball_dir: Vec2,
+    pub ball_speed: f32,
+    pub boxes: [[Brick; BOX_PER_LINE]; BOX_LINE_COUNT],
+    pub enemies: Enemies,
+    pub boss: Option<Boss>,
+    pub shrink_timer: f32,
+    pub stun_timer: f32,
+    pub drops: Drops,
+    /// How long the warp gate in the right wall is going to stay open
+    pub warp_timer: f32,
+    /// Set once the paddle has gone through the warp gate
+    pub warped: bool,
+    pub score: u32,
+    /// How many bricks got broken so far
+    pub broken: u32,
+    /// Bricks broken since the ball last touched a paddle
+    #[nserde(default)]
+    pub combo: u32,
+    /// How many garbage rows got pushed in so far
+    #[nserde(default)]
+    pub garbage_rows: u32,
+    /// Only the physics draws from it
+    pub(crate) rng: Rng,
+}
+
+/// A single attempt at a mode, from the start menu to the last stage
+#[derive(Clone, Debug, DeJson, SerJson)]
+pub struct Run {
+    pub mode: GameMode,
+    /// Where the generated levels of the run come from
+    pub seed: u64,
+    pub stage: usize,
+    /// Lives left, shared by everyone playing
+    pub lives: Option<u32>,
+    /// Time spent actually playing, pauses and screens between stages don't count
+    pub time: f32,
+    /// The run time at the moment each stage got cleared
+    pub splits: Vec<f32>,
+    pub over: bool,
+}
+
+/// What the library or a theme file changes about a particle effect. Everything is optional.
+#[derive(Clone, Debug, DeJson)]
+pub struct ParticleStyle {
+    pub amount: Option<u32>,
+    pub lifetime: Option<f32>,
+    pub lifetime_randomness: Option<f32>,
+    /// 0 spreads the particles over the lifetime, 1 lets them all out at once
+    pub explosiveness: Option<f32>,
+    pub initial_velocity: Option<f32>,
+    pub initial_velocity_randomness: Option<f32>,
+    /// In radians
+    pub initial_direction_spread: Option<f32>,
+    pub initial_angular_velocity: Option<f32>,
+    pub size: Option<f32>,
+    pub size_randomness: Option<f32>,
+    pub gravity: Option<Vec<f32>>,
+    /// Adds the colours up instead of blending them
+    pub additive: Option<bool>,
+    /// The start, middle and end colours
+    pub colors: Option<Vec<String>>,
+}
@@ -0,0 +1,382 @@
+use macroquad::prelude::*;
+use nanoserde::{DeJson, SerJson};
+
+use crate::{input::{ControlMode, PLAYER_KEYS}, post::PostSettings, render::ParticleQuality, sys, theme::{ColorVision, DEFAULT_THEME}};
+
+pub const SETTINGS_KEY: &str = "settings";
+/// Bump this whenever an existing option changes meaning and teach
+/// [Settings::migrate] how to convert the old one
+pub const SETTINGS_VERSION: u32 = 2;
+
+/// How many notches the volume levels have above zero
+pub const VOLUME_STEPS: i32 = 10;
+
+/// Keys the paddles can be bound to. They are written down by their `Debug` names.
+/// M is left out, it mutes the sound.
+const BINDABLE_KEYS: [KeyCode; 29] = [
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F,
+    KeyCode::G, KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L,
+    KeyCode::N, KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R,
+    KeyCode::S, KeyCode::T, KeyCode::U, KeyCode::V, KeyCode::W, KeyCode::X,
+    KeyCode::Y, KeyCode::Z,
+    KeyCode::Left, KeyCode::Right, KeyCode::Up, KeyCode::Down,
+];
+
+/// Left and right keys of both players, by name
+#[derive(Clone, Debug, PartialEq, Eq, DeJson, SerJson)]
+pub struct Bindings {
+    pub left: [String; 2],
+    pub right: [String; 2],
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self {
+            left: PLAYER_KEYS.map(|(left, _)| key_name(left)),
+            right: PLAYER_KEYS.map(|(_, right)| key_name(right)),
+        }
+    }
+}
+
+impl Bindings {
+    /// Keys that can't be bound fall back to the default ones
+    pub fn player_keys(&self) -> [(KeyCode, KeyCode); 2] {
+        [0, 1].map(|idx| (
+            key_by_name(&self.left[idx]).unwrap_or(PLAYER_KEYS[idx].0),
+            key_by_name(&self.right[idx]).unwrap_or(PLAYER_KEYS[idx].1),
+        ))
+    }
+}
+
+pub fn key_name(key: KeyCode) -> String {
+    format!("{key:?}")
+}
+
+pub fn key_by_name(name: &str) -> Option<KeyCode> {
+    BINDABLE_KEYS.into_iter().find(|x| key_name(*x) == name)
+}
+
+/// Everything the player picks once and expects to stay that way.
+/// Options missing from the stored file get their defaults.
+#[derive(Clone, Debug, PartialEq, DeJson, SerJson)]
+pub struct Settings {
+    #[nserde(default)]
+    pub version: u32,
+    /// Bus levels from 0 to 1. The master level scales both of the others.
+    #[nserde(default = "1.0")]
+    pub master_volume: f32,
+    #[nserde(default = "1.0")]
+    pub sfx_volume: f32,
+    #[nserde(default = "1.0")]
+    pub music_volume: f32,
+    #[nserde(default)]
+    pub muted: bool,
+    #[nserde(default)]
+    pub fullscreen: bool,
+    #[nserde(default)]
+    pub control_mode: ControlMode,
+    #[nserde(default)]
+    pub bindings: Bindings,
+    #[nserde(default)]
+    pub particle_quality: ParticleQuality,
+    /// Tones down screen shake, freeze frames and zooming
+    #[nserde(default)]
+    pub reduce_motion: bool,
+    #[nserde(default)]
+    pub post: PostSettings,
+    /// Levels that name a theme of their own override this one
+    #[nserde(default = "classic")]
+    pub theme: String,
+    #[nserde(default)]
+    pub color_vision: ColorVision,
+    #[nserde(default)]
+    pub high_contrast: bool,
+    /// Marks every kind of brick with a pattern of its own
+    #[nserde(default)]
+    pub brick_patterns: bool,
+    /// Only English is there so far
+    #[nserde(default = "en")]
+    pub language: String,
+}
+
+/// Rows of the settings screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingsEntry {
+    Master,
+    Sfx,
+    Music,
+    Mute,
+    ReduceMotion,
+    ScreenEffects,
+    Crt,
+    Bloom,
+    Aberration,
+    Vignette,
+    Theme,
+    Colors,
+    HighContrast,
+    BrickPatterns,
+    Back,
+}
+
+impl SettingsEntry {
+    pub const ALL: [SettingsEntry; 15] = [
+        SettingsEntry::Master,
+        SettingsEntry::Sfx,
+        SettingsEntry::Music,
+        SettingsEntry::Mute,
+        SettingsEntry::ReduceMotion,
+        SettingsEntry::ScreenEffects,
+        SettingsEntry::Crt,
+        SettingsEntry::Bloom,
+        SettingsEntry::Aberration,
+        SettingsEntry::Vignette,
+        SettingsEntry::Theme,
+        SettingsEntry::Colors,
+        SettingsEntry::HighContrast,
+        SettingsEntry::BrickPatterns,
+        SettingsEntry::Back,
+    ];
+
+    pub fn label(self, settings: &Settings) -> String {
+        let level = |x: f32| format!("{}%", (x * 100.0).round() as u32);
+
+        match self {
+            SettingsEntry::Master => format!("Volume  {}", level(settings.master_volume)),
+            SettingsEntry::Sfx => format!("Effects  {}", level(settings.sfx_volume)),
+            SettingsEntry::Music => format!("Music  {}", level(settings.music_volume)),
+            SettingsEntry::Mute if settings.muted => "Sound  off".to_owned(),
+            SettingsEntry::Mute => "Sound  on".to_owned(),
+            SettingsEntry::ReduceMotion if settings.reduce_motion => "Reduce motion  on".to_owned(),
+            SettingsEntry::ReduceMotion => "Reduce motion  off".to_owned(),
+            SettingsEntry::ScreenEffects if settings.post.enabled => "Screen effects  on".to_owned(),
+            SettingsEntry::ScreenEffects => "Screen effects  off".to_owned(),
+            SettingsEntry::Crt if settings.post.crt => "CRT  on".to_owned(),
+            SettingsEntry::Crt => "CRT  off".to_owned(),
+            SettingsEntry::Bloom if settings.post.bloom => "Bloom  on".to_owned(),
+            SettingsEntry::Bloom => "Bloom  off".to_owned(),
+            SettingsEntry::Aberration if settings.post.aberration => "Aberration  on".to_owned(),
+            SettingsEntry::Aberration => "Aberration  off".to_owned(),
+            SettingsEntry::Vignette if settings.post.vignette => "Vignette  on".to_owned(),
+            SettingsEntry::Vignette => "Vignette  off".to_owned(),
+            SettingsEntry::Theme => format!("Theme  {}", settings.theme),
+            SettingsEntry::Colors => format!("Colours  {}", settings.color_vision.name()),
+            SettingsEntry::HighContrast if settings.high_contrast => "High contrast  on".to_owned(),
+            SettingsEntry::HighContrast => "High contrast  off".to_owned(),
+            SettingsEntry::BrickPatterns if settings.brick_patterns => "Brick patterns  on".to_owned(),
+            SettingsEntry::BrickPatterns => "Brick patterns  off".to_owned(),
+            SettingsEntry::Back => "Back".to_owned(),
+        }
+    }
+}
+
+/// What version 1 had that later ones don't
+#[derive(DeJson)]
+struct SettingsV1 {
+    #[nserde(default = "1.0")]
+    volume: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            master_volume: 1.0,
+            sfx_volume: 1.0,
+            music_volume: 1.0,
+            muted: false,
+            fullscreen: false,
+            control_mode: ControlMode::default(),
+            bindings: Bindings::default(),
+            particle_quality: ParticleQuality::default(),
+            reduce_motion: false,
+            post: PostSettings::default(),
+            theme: DEFAULT_THEME.to_owned(),
+            color_vision: ColorVision::default(),
+            high_contrast: false,
+            brick_patterns: false,
+            language: "en".to_owned(),
+        }
+    }
+}
+
+impl Settings {
+    /// Unreadable settings are not worth refusing to start over
+    pub fn load() -> Self {
+        let Some(data) = sys::storage_load(SETTINGS_KEY) else {
+            return Self::default();
+        };
+
+        match Self::deserialize_json(&data) {
+            Ok(mut settings) => {
+                settings.migrate(&data);
+                settings
+            },
+            Err(e) => {
+                warn!("Using the default settings: {e}");
+                Self::default()
+            },
+        }
+    }
+
+    fn level_mut(&mut self, entry: SettingsEntry) -> Option<&mut f32> {
+        match entry {
+            SettingsEntry::Master => Some(&mut self.master_volume),
+            SettingsEntry::Sfx => Some(&mut self.sfx_volume),
+            SettingsEntry::Music => Some(&mut self.music_volume),
+            SettingsEntry::Mute | SettingsEntry::ReduceMotion | SettingsEntry::ScreenEffects |
+            SettingsEntry::Crt | SettingsEntry::Bloom | SettingsEntry::Aberration |
+            SettingsEntry::Vignette | SettingsEntry::Theme | SettingsEntry::Colors |
+            SettingsEntry::HighContrast | SettingsEntry::BrickPatterns | SettingsEntry::Back => None,
+        }
+    }
+
+    fn switch_mut(&mut self, entry: SettingsEntry) -> Option<&mut bool> {
+        match entry {
+            SettingsEntry::Mute => Some(&mut self.muted),
+            SettingsEntry::ReduceMotion => Some(&mut self.reduce_motion),
+            SettingsEntry::ScreenEffects => Some(&mut self.post.enabled),
+            SettingsEntry::Crt => Some(&mut self.post.crt),
+            SettingsEntry::Bloom => Some(&mut self.post.bloom),
+            SettingsEntry::Aberration => Some(&mut self.post.aberration),
+            SettingsEntry::Vignette => Some(&mut self.post.vignette),
+            SettingsEntry::HighContrast => Some(&mut self.high_contrast),
+            SettingsEntry::BrickPatterns => Some(&mut self.brick_patterns),
+            SettingsEntry::Master | SettingsEntry::Sfx | SettingsEntry::Music |
+            SettingsEntry::Theme | SettingsEntry::Colors | SettingsEntry::Back => None,
+        }
+    }
+
+    /// Turns the entry down or up by a number of notches, like the arrow keys do
+    pub fn adjust(&mut self, entry: SettingsEntry, steps: i32) {
+        if let Some(switch) = self.switch_mut(entry).filter(|_| steps != 0) {
+            *switch = !*switch;
+        }
+
+        if entry == SettingsEntry::Colors {
+            self.color_vision = self.color_vision.step(steps);
+        }
+
+        if let Some(level) = self.level_mut(entry) {
+            let notch = (*level * VOLUME_STEPS as f32).round() as i32 + steps;
+            *level = notch.clamp(0, VOLUME_STEPS) as f32 / VOLUME_STEPS as f32;
+        }
+    }
+
+    /// Turns the entry up by a notch, going around to zero past the top.
+    /// Clicks and the confirm button have no other direction.
+    pub fn cycle(&mut self, entry: SettingsEntry) {
+        if let Some(switch) = self.switch_mut(entry) {
+            *switch = !*switch;
+        }
+
+        if entry == SettingsEntry::Colors {
+            self.color_vision = self.color_vision.step(1);
+        }
+
+        if let Some(level) = self.level_mut(entry) {
+            let notch = (*level * VOLUME_STEPS as f32).round() as i32 + 1;
+            *level = notch.rem_euclid(VOLUME_STEPS + 1) as f32 / VOLUME_STEPS as f32;
+        }
+    }
+
+    /// Moves a number of themes along `ids`, going around at the ends.
+    /// A theme that is gone counts as the first one.
+    pub fn step_theme(&mut self, ids: &[String], steps: i32) {
+        if ids.is_empty() {
+            return;
+        }
+
+        let current = ids.iter().position(|x| *x == self.theme).unwrap_or_default();
+        let next = (current as i32 + steps).rem_euclid(ids.len() as i32) as usize;
+        self.theme = ids[next].clone();
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        sys::storage_store(SETTINGS_KEY, &self.serialize_json())
+    }
+
+    fn migrate(&mut self, data: &str) {
+        match self.version {
+            SETTINGS_VERSION => (),
+            x if x > SETTINGS_VERSION => {
+                warn!("The settings are from a newer version of the game ({x}), unknown options get dropped");
+            },
+            // The single volume of version 1 became the master level
+            1 => match SettingsV1::deserialize_json(data) {
+                Ok(old) => self.master_volume = old.volume,
+                Err(e) => warn!("Dropping the old volume: {e}"),
+            },
+            // Files from before versioning only miss options, which got their defaults already
+            _ => (),
+        }
+
+        for level in [&mut self.master_volume, &mut self.sfx_volume, &mut self.music_volume] {
+            *level = level.clamp(0.0, 1.0);
+        }
+        self.version = SETTINGS_VERSION;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(data: &str) -> Settings {
+        let mut settings = Settings::deserialize_json(data).unwrap();
+        settings.migrate(data);
+
+        settings
+    }
+
+    #[test]
+    fn unversioned_file_gets_defaults() {
+        let settings = parse(r#"{"muted":true}"#);
+
+        assert_eq!(settings, Settings { muted: true, ..Settings::default() });
+    }
+
+    #[test]
+    fn unversioned_file_keeps_its_volumes() {
+        let settings = parse(r#"{"master_volume":0.3,"music_volume":0.6}"#);
+
+        assert_eq!(settings, Settings {
+            master_volume: 0.3,
+            music_volume: 0.6,
+            ..Settings::default()
+        });
+    }
+
+    #[test]
+    fn version_one_volume_becomes_master() {
+        let settings = parse(r#"{"version":1,"volume":0.4}"#);
+
+        assert_eq!(settings, Settings { master_volume: 0.4, ..Settings::default() });
+    }
+
+    #[test]
+    fn version_one_volume_gets_clamped() {
+        assert_eq!(parse(r#"{"version":1,"volume":3.0}"#).master_volume, 1.0);
+        assert_eq!(parse(r#"{"version":1,"volume":-1.0}"#).master_volume, 0.0);
+    }
+
+    #[test]
+    fn current_version_is_kept() {
+        let settings = Settings {
+            sfx_volume: 0.3,
+            theme: "neon".to_owned(),
+            ..Settings::default()
+        };
+
+        assert_eq!(parse(&settings.serialize_json()), settings);
+        let loud = format!(r#"{{"version":{SETTINGS_VERSION},"music_volume":7.0}}"#);
+        assert_eq!(parse(&loud).music_volume, 1.0);
+    }
+
+    #[test]
+    fn newer_version_keeps_known_options() {
+        let settings = parse(r#"{"version":9,"sfx_volume":0.5,"hover_cars":true}"#);
+
+        assert_eq!(settings, Settings { sfx_volume: 0.5, ..Settings::default() });
+    }
+}
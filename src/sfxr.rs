@@ -0,0 +1,580 @@
+use anyhow::bail;
+
+use crate::{mixer::Samples, rng::Rng};
+
+/// sfxr always synthesizes at this rate
+pub const SFXR_RATE: u32 = 44100;
+/// The volume slider of sfxr at its default
+const MASTER_VOL: f32 = 0.05;
+/// The gain sfxr applies when it exports a wav
+const EXPORT_GAIN: f32 = 4.0;
+/// Nothing gets longer than this, whatever the envelope says
+const MAX_LENGTH: usize = SFXR_RATE as usize * 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaveType {
+    Square,
+    Sawtooth,
+    Sine,
+    Noise,
+}
+
+impl WaveType {
+    fn from_id(id: i32) -> Option<Self> {
+        match id {
+            0 => Some(WaveType::Square),
+            1 => Some(WaveType::Sawtooth),
+            2 => Some(WaveType::Sine),
+            3 => Some(WaveType::Noise),
+            _ => None,
+        }
+    }
+}
+
+/// The sliders of sfxr, with the same names and ranges. Most go from 0 to 1,
+/// the ramps and the phaser from -1 to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SfxrParams {
+    pub wave_type: WaveType,
+    pub sound_vol: f32,
+    pub base_freq: f32,
+    pub freq_limit: f32,
+    pub freq_ramp: f32,
+    pub freq_dramp: f32,
+    pub duty: f32,
+    pub duty_ramp: f32,
+    pub vib_strength: f32,
+    pub vib_speed: f32,
+    pub vib_delay: f32,
+    pub env_attack: f32,
+    pub env_sustain: f32,
+    pub env_decay: f32,
+    pub env_punch: f32,
+    pub filter_on: bool,
+    pub lpf_resonance: f32,
+    pub lpf_freq: f32,
+    pub lpf_ramp: f32,
+    pub hpf_freq: f32,
+    pub hpf_ramp: f32,
+    pub pha_offset: f32,
+    pub pha_ramp: f32,
+    pub repeat_speed: f32,
+    pub arp_speed: f32,
+    pub arp_mod: f32,
+}
+
+impl Default for SfxrParams {
+    /// What sfxr starts out with
+    fn default() -> Self {
+        Self {
+            wave_type: WaveType::Square,
+            sound_vol: 0.5,
+            base_freq: 0.3,
+            freq_limit: 0.0,
+            freq_ramp: 0.0,
+            freq_dramp: 0.0,
+            duty: 0.0,
+            duty_ramp: 0.0,
+            vib_strength: 0.0,
+            vib_speed: 0.0,
+            vib_delay: 0.0,
+            env_attack: 0.0,
+            env_sustain: 0.3,
+            env_decay: 0.4,
+            env_punch: 0.0,
+            filter_on: false,
+            lpf_resonance: 0.0,
+            lpf_freq: 1.0,
+            lpf_ramp: 0.0,
+            hpf_freq: 0.0,
+            hpf_ramp: 0.0,
+            pha_offset: 0.0,
+            pha_ramp: 0.0,
+            repeat_speed: 0.0,
+            arp_speed: 0.0,
+            arp_mod: 0.0,
+        }
+    }
+}
+
+impl SfxrParams {
+    /// The ball bouncing off anything that doesn't break
+    pub fn ball() -> Self {
+        Self {
+            sound_vol: 1.0,
+            base_freq: 0.42,
+            duty: 0.35,
+            env_sustain: 0.1,
+            env_decay: 0.16,
+            hpf_freq: 0.1,
+            ..Self::default()
+        }
+    }
+
+    /// A brick or an enemy getting smashed
+    pub fn brick_break() -> Self {
+        Self {
+            wave_type: WaveType::Noise,
+            base_freq: 0.48,
+            freq_ramp: -0.32,
+            env_sustain: 0.2,
+            env_decay: 0.31,
+            env_punch: 0.3,
+            hpf_freq: 0.05,
+            ..Self::default()
+        }
+    }
+
+    /// The ball falling through the floor for good
+    pub fn dead() -> Self {
+        Self {
+            wave_type: WaveType::Sawtooth,
+            base_freq: 0.45,
+            freq_ramp: -0.28,
+            vib_strength: 0.25,
+            vib_speed: 0.45,
+            env_sustain: 0.22,
+            env_decay: 0.3,
+            env_punch: 0.2,
+            ..Self::default()
+        }
+    }
+
+    /// Reads the binary files sfxr saves, versions 100 to 102
+    pub fn from_sfxr_file(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut file = SfxrReader(bytes);
+
+        let version = file.int()?;
+        if !(100..=102).contains(&version) {
+            bail!("Unsupported sfxr file version {version}");
+        }
+        let wave_type = file.int()?;
+        let Some(wave_type) = WaveType::from_id(wave_type) else {
+            bail!("Unknown sfxr wave type {wave_type}");
+        };
+
+        let mut res = Self { wave_type, ..Self::default() };
+        if version == 102 {
+            res.sound_vol = file.float()?;
+        }
+        res.base_freq = file.float()?;
+        res.freq_limit = file.float()?;
+        res.freq_ramp = file.float()?;
+        if version >= 101 {
+            res.freq_dramp = file.float()?;
+        }
+        res.duty = file.float()?;
+        res.duty_ramp = file.float()?;
+        res.vib_strength = file.float()?;
+        res.vib_speed = file.float()?;
+        res.vib_delay = file.float()?;
+        res.env_attack = file.float()?;
+        res.env_sustain = file.float()?;
+        res.env_decay = file.float()?;
+        res.env_punch = file.float()?;
+        res.filter_on = file.bytes(1)?[0] != 0;
+        res.lpf_resonance = file.float()?;
+        res.lpf_freq = file.float()?;
+        res.lpf_ramp = file.float()?;
+        res.hpf_freq = file.float()?;
+        res.hpf_ramp = file.float()?;
+        res.pha_offset = file.float()?;
+        res.pha_ramp = file.float()?;
+        res.repeat_speed = file.float()?;
+        if version >= 101 {
+            res.arp_speed = file.float()?;
+            res.arp_mod = file.float()?;
+        }
+
+        Ok(res)
+    }
+
+    /// Renders the sound the way sfxr exports it. The noise comes from `seed`,
+    /// so the same parameters and seed always give the same samples.
+    pub fn synth(&self, seed: u64) -> Samples {
+        let mut voice = Voice::new(self, seed);
+        let mut data = Vec::new();
+
+        while voice.playing && data.len() < MAX_LENGTH {
+            data.push(voice.sample());
+        }
+
+        Samples { rate: SFXR_RATE, data }
+    }
+}
+
+/// Whatever is left of an sfxr file. It is a dump of the C variables, so everything is little endian.
+struct SfxrReader<'a>(&'a [u8]);
+
+impl SfxrReader<'_> {
+    fn bytes(&mut self, len: usize) -> anyhow::Result<&[u8]> {
+        if self.0.len() < len {
+            bail!("The sfxr file is truncated");
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+
+        Ok(head)
+    }
+
+    fn int(&mut self) -> anyhow::Result<i32> {
+        Ok(i32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn float(&mut self) -> anyhow::Result<f32> {
+        Ok(f32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+}
+
+/// The synthesizer state of sfxr, field for field
+struct Voice<'a> {
+    params: &'a SfxrParams,
+    rng: Rng,
+    playing: bool,
+    phase: i32,
+    fperiod: f64,
+    fmaxperiod: f64,
+    fslide: f64,
+    fdslide: f64,
+    period: i32,
+    square_duty: f32,
+    square_slide: f32,
+    env_stage: usize,
+    env_time: i32,
+    env_length: [i32; 3],
+    env_vol: f32,
+    fphase: f32,
+    fdphase: f32,
+    iphase: i32,
+    phaser_buffer: [f32; 1024],
+    ipp: i32,
+    noise_buffer: [f32; 32],
+    fltp: f32,
+    fltdp: f32,
+    fltw: f32,
+    fltw_d: f32,
+    fltdmp: f32,
+    fltphp: f32,
+    flthp: f32,
+    flthp_d: f32,
+    vib_phase: f32,
+    vib_speed: f32,
+    vib_amp: f32,
+    rep_time: i32,
+    rep_limit: i32,
+    arp_time: i32,
+    arp_limit: i32,
+    arp_mod: f64,
+}
+
+impl<'a> Voice<'a> {
+    fn new(params: &'a SfxrParams, seed: u64) -> Self {
+        let mut res = Self {
+            params,
+            rng: Rng::new(seed),
+            playing: true,
+            phase: 0,
+            fperiod: 0.0,
+            fmaxperiod: 0.0,
+            fslide: 0.0,
+            fdslide: 0.0,
+            period: 0,
+            square_duty: 0.0,
+            square_slide: 0.0,
+            env_stage: 0,
+            env_time: 0,
+            env_length: [0; 3],
+            env_vol: 0.0,
+            fphase: 0.0,
+            fdphase: 0.0,
+            iphase: 0,
+            phaser_buffer: [0.0; 1024],
+            ipp: 0,
+            noise_buffer: [0.0; 32],
+            fltp: 0.0,
+            fltdp: 0.0,
+            fltw: 0.0,
+            fltw_d: 0.0,
+            fltdmp: 0.0,
+            fltphp: 0.0,
+            flthp: 0.0,
+            flthp_d: 0.0,
+            vib_phase: 0.0,
+            vib_speed: 0.0,
+            vib_amp: 0.0,
+            rep_time: 0,
+            rep_limit: 0,
+            arp_time: 0,
+            arp_limit: 0,
+            arp_mod: 0.0,
+        };
+        res.reset(false);
+
+        res
+    }
+
+    /// sfxr's `frnd`
+    fn frnd(&mut self, range: f32) -> f32 {
+        self.rng.next_f32() * range
+    }
+
+    fn reset(&mut self, restart: bool) {
+        let p = self.params;
+
+        if !restart {
+            self.phase = 0;
+        }
+        self.fperiod = 100.0 / (p.base_freq as f64 * p.base_freq as f64 + 0.001);
+        self.period = self.fperiod as i32;
+        self.fmaxperiod = 100.0 / (p.freq_limit as f64 * p.freq_limit as f64 + 0.001);
+        self.fslide = 1.0 - (p.freq_ramp as f64).powi(3) * 0.01;
+        self.fdslide = -(p.freq_dramp as f64).powi(3) * 0.000001;
+        self.square_duty = 0.5 - p.duty * 0.5;
+        self.square_slide = -p.duty_ramp * 0.00005;
+        self.arp_mod = if p.arp_mod >= 0.0 {
+            1.0 - (p.arp_mod as f64).powi(2) * 0.9
+        } else {
+            1.0 + (p.arp_mod as f64).powi(2) * 10.0
+        };
+        self.arp_time = 0;
+        self.arp_limit = ((1.0 - p.arp_speed).powi(2) * 20000.0 + 32.0) as i32;
+        if p.arp_speed == 1.0 {
+            self.arp_limit = 0;
+        }
+
+        if restart {
+            return;
+        }
+
+        self.fltp = 0.0;
+        self.fltdp = 0.0;
+        self.fltw = p.lpf_freq.powi(3) * 0.1;
+        self.fltw_d = 1.0 + p.lpf_ramp * 0.0001;
+        self.fltdmp = (5.0 / (1.0 + p.lpf_resonance.powi(2) * 20.0) * (0.01 + self.fltw)).min(0.8);
+        self.fltphp = 0.0;
+        self.flthp = p.hpf_freq.powi(2) * 0.1;
+        self.flthp_d = 1.0 + p.hpf_ramp * 0.0003;
+
+        self.vib_phase = 0.0;
+        self.vib_speed = p.vib_speed.powi(2) * 0.01;
+        self.vib_amp = p.vib_strength * 0.5;
+
+        self.env_vol = 0.0;
+        self.env_stage = 0;
+        self.env_time = 0;
+        self.env_length = [p.env_attack, p.env_sustain, p.env_decay]
+            .map(|x| (x * x * 100000.0) as i32);
+
+        self.fphase = p.pha_offset.powi(2) * 1020.0 * p.pha_offset.signum();
+        self.fdphase = p.pha_ramp.powi(2) * p.pha_ramp.signum();
+        self.iphase = (self.fphase as i32).abs();
+        self.ipp = 0;
+        self.phaser_buffer = [0.0; 1024];
+        for idx in 0..self.noise_buffer.len() {
+            self.noise_buffer[idx] = self.frnd(2.0) - 1.0;
+        }
+
+        self.rep_time = 0;
+        self.rep_limit = ((1.0 - p.repeat_speed).powi(2) * 20000.0 + 32.0) as i32;
+        if p.repeat_speed == 0.0 {
+            self.rep_limit = 0;
+        }
+    }
+
+    fn sample(&mut self) -> f32 {
+        let p = self.params;
+
+        self.rep_time += 1;
+        if self.rep_limit != 0 && self.rep_time >= self.rep_limit {
+            self.rep_time = 0;
+            self.reset(true);
+        }
+
+        self.arp_time += 1;
+        if self.arp_limit != 0 && self.arp_time >= self.arp_limit {
+            self.arp_limit = 0;
+            self.fperiod *= self.arp_mod;
+        }
+        self.fslide += self.fdslide;
+        self.fperiod *= self.fslide;
+        if self.fperiod > self.fmaxperiod {
+            self.fperiod = self.fmaxperiod;
+            if p.freq_limit > 0.0 {
+                self.playing = false;
+            }
+        }
+        let mut rfperiod = self.fperiod as f32;
+        if self.vib_amp > 0.0 {
+            self.vib_phase += self.vib_speed;
+            rfperiod = (self.fperiod * (1.0 + (self.vib_phase.sin() * self.vib_amp) as f64)) as f32;
+        }
+        self.period = (rfperiod as i32).max(8);
+        self.square_duty = (self.square_duty + self.square_slide).clamp(0.0, 0.5);
+
+        self.env_time += 1;
+        if self.env_time > self.env_length[self.env_stage] {
+            self.env_time = 0;
+            self.env_stage += 1;
+            if self.env_stage == 3 {
+                self.playing = false;
+            }
+        }
+        // sfxr divides by zero for empty stages, which only ever matters for one sample
+        let progress = |stage: usize| self.env_time as f32 / self.env_length[stage].max(1) as f32;
+        match self.env_stage {
+            0 => self.env_vol = progress(0),
+            1 => self.env_vol = 1.0 + (1.0 - progress(1)) * 2.0 * p.env_punch,
+            2 => self.env_vol = 1.0 - progress(2),
+            _ => (),
+        }
+
+        self.fphase += self.fdphase;
+        self.iphase = (self.fphase as i32).abs().min(1023);
+
+        if self.flthp_d != 0.0 {
+            self.flthp = (self.flthp * self.flthp_d).clamp(0.00001, 0.1);
+        }
+
+        // 8x supersampling
+        let mut ssample = 0.0;
+        for _ in 0..8 {
+            self.phase += 1;
+            if self.phase >= self.period {
+                self.phase %= self.period;
+                if p.wave_type == WaveType::Noise {
+                    for idx in 0..self.noise_buffer.len() {
+                        self.noise_buffer[idx] = self.frnd(2.0) - 1.0;
+                    }
+                }
+            }
+
+            let fp = self.phase as f32 / self.period as f32;
+            let mut sample = match p.wave_type {
+                WaveType::Square if fp < self.square_duty => 0.5,
+                WaveType::Square => -0.5,
+                WaveType::Sawtooth => 1.0 - fp * 2.0,
+                WaveType::Sine => (fp * 2.0 * std::f32::consts::PI).sin(),
+                WaveType::Noise => self.noise_buffer[(self.phase * 32 / self.period) as usize],
+            };
+
+            // Low-pass filter
+            let pp = self.fltp;
+            self.fltw = (self.fltw * self.fltw_d).clamp(0.0, 0.1);
+            if p.lpf_freq != 1.0 {
+                self.fltdp += (sample - self.fltp) * self.fltw;
+                self.fltdp -= self.fltdp * self.fltdmp;
+            } else {
+                self.fltp = sample;
+                self.fltdp = 0.0;
+            }
+            self.fltp += self.fltdp;
+
+            // High-pass filter
+            self.fltphp += self.fltp - pp;
+            self.fltphp -= self.fltphp * self.flthp;
+            sample = self.fltphp;
+
+            // Phaser
+            self.phaser_buffer[(self.ipp & 1023) as usize] = sample;
+            sample += self.phaser_buffer[((self.ipp - self.iphase + 1024) & 1023) as usize];
+            self.ipp = (self.ipp + 1) & 1023;
+
+            ssample += sample * self.env_vol;
+        }
+
+        let ssample = ssample / 8.0 * MASTER_VOL * 2.0 * p.sound_vol;
+
+        (ssample * EXPORT_GAIN).clamp(-1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How far apart the samples are picked from the reference renders
+    const STRIDE: usize = 64;
+    /// The references come from a line by line Python port of sfxr, which
+    /// computes everything in double precision
+    const TOLERANCE: f32 = 1e-4;
+
+    const BALL_LENGTH: usize = 3563;
+    const BALL_REFERENCE: [f32; 56] = [
+        0.398204, -0.333881, -0.300834, -0.286310, -0.283161, -0.287118, -0.295637, -0.307213,
+        0.497257, 0.524689, 0.552995, 0.582447, -0.222350, -0.233809, -0.245941, -0.258751,
+        -0.269812, -0.276751, -0.283689, 0.457399, 0.468211, 0.478900, 0.489423, -0.180755,
+        -0.184389, -0.187909, -0.191292, -0.194515, -0.197550, -0.200371, 0.319449, 0.323061,
+        0.326176, 0.328732, -0.119600, -0.120045, -0.120209, -0.120061, -0.119568, -0.118694,
+        -0.117402, 0.182039, 0.178484, 0.174058, 0.168679, -0.058689, -0.055957, -0.052778,
+        -0.049113, -0.044920, -0.040153, -0.034764, 0.045176, 0.034483, 0.022546, 0.009264,
+    ];
+
+    const BREAK_LENGTH: usize = 13613;
+    /// The beginning of the break sound, which is noise from an [Rng] seeded with zero
+    const BREAK_REFERENCE: [f32; 48] = [
+        0.460019, 0.074043, -0.236202, -0.307487, 0.072358, -0.137310, -0.049597, 0.279802,
+        0.365772, 0.501363, -0.086711, 0.032068, -0.194144, -0.308274, 0.212814, -0.030346,
+        -0.515763, 0.270155, -0.338944, 0.063637, 0.070225, 0.021745, 0.312938, 0.461303,
+        -0.292181, -0.187282, -0.407391, -0.242237, -0.056663, 0.375201, 0.291785, -0.265885,
+        -0.196689, 0.084699, 0.315611, 0.168391, 0.183554, -0.273358, 0.001769, -0.044070,
+        0.236470, 0.466881, -0.132537, 0.289415, 0.136242, -0.439943, -0.444152, -0.163405,
+    ];
+
+    fn assert_matches(samples: &Samples, length: usize, reference: &[f32]) {
+        assert_eq!(samples.rate, SFXR_RATE);
+        assert_eq!(samples.data.len(), length);
+
+        let picked = samples.data.iter().step_by(STRIDE);
+        for (idx, (got, expected)) in picked.zip(reference).enumerate() {
+            assert!(
+                (got - expected).abs() < TOLERANCE,
+                "sample {}: got {got}, expected {expected}",
+                idx * STRIDE,
+            );
+        }
+    }
+
+    #[test]
+    fn ball_matches_reference() {
+        assert_matches(&SfxrParams::ball().synth(0), BALL_LENGTH, &BALL_REFERENCE);
+    }
+
+    #[test]
+    fn break_matches_reference() {
+        assert_matches(&SfxrParams::brick_break().synth(0), BREAK_LENGTH, &BREAK_REFERENCE);
+    }
+
+    #[test]
+    fn synthesis_is_deterministic() {
+        for params in [SfxrParams::ball(), SfxrParams::brick_break(), SfxrParams::dead()] {
+            assert_eq!(params.synth(7).data, params.synth(7).data);
+        }
+    }
+
+    #[test]
+    fn sfxr_file_round_trip() {
+        let params = SfxrParams::dead();
+        let mut file = Vec::new();
+        file.extend_from_slice(&102i32.to_le_bytes());
+        file.extend_from_slice(&1i32.to_le_bytes());
+        for value in [
+            params.sound_vol, params.base_freq, params.freq_limit, params.freq_ramp,
+            params.freq_dramp, params.duty, params.duty_ramp, params.vib_strength,
+            params.vib_speed, params.vib_delay, params.env_attack, params.env_sustain,
+            params.env_decay, params.env_punch,
+        ] {
+            file.extend_from_slice(&value.to_le_bytes());
+        }
+        file.push(params.filter_on as u8);
+        for value in [
+            params.lpf_resonance, params.lpf_freq, params.lpf_ramp, params.hpf_freq,
+            params.hpf_ramp, params.pha_offset, params.pha_ramp, params.repeat_speed,
+            params.arp_speed, params.arp_mod,
+        ] {
+            file.extend_from_slice(&value.to_le_bytes());
+        }
+
+        assert_eq!(SfxrParams::from_sfxr_file(&file).unwrap(), params);
+        assert!(SfxrParams::from_sfxr_file(&file[..file.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use macroquad::prelude::*;
+use nanoserde::DeJson;
+
+use crate::{
+    mixer::{Samples, VariedSound},
+    physics::Brick,
+    sfxr::SfxrParams,
+};
+
+/// Which sounds play for which events
+pub const MANIFEST_PATH: &str = "assets/sounds.json";
+/// The noise of the synthesized effects, so that they come out the same every time
+const SFX_SEED: u64 = 0;
+/// How far the pitch climbs with each brick of a combo, in semitones
+pub const COMBO_SEMITONES: [f32; 6] = [0.0, 2.0, 4.0, 7.0, 9.0, 12.0];
+
+/// Everything in the game that makes a sound
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoundEvent {
+    Bounce,
+    PaddleStruck,
+    WarpOpened,
+    Break,
+    BreakTough,
+    BreakCracked,
+    BreakExplosive,
+    EnemyKilled,
+    BossHit,
+    GameOver,
+}
+
+impl SoundEvent {
+    pub const ALL: [SoundEvent; 10] = [
+        SoundEvent::Bounce,
+        SoundEvent::PaddleStruck,
+        SoundEvent::WarpOpened,
+        SoundEvent::Break,
+        SoundEvent::BreakTough,
+        SoundEvent::BreakCracked,
+        SoundEvent::BreakExplosive,
+        SoundEvent::EnemyKilled,
+        SoundEvent::BossHit,
+        SoundEvent::GameOver,
+    ];
+
+    /// The key of the event in the manifest
+    pub fn name(self) -> &'static str {
+        match self {
+            SoundEvent::Bounce => "bounce",
+            SoundEvent::PaddleStruck => "paddle_struck",
+            SoundEvent::WarpOpened => "warp_opened",
+            SoundEvent::Break => "break",
+            SoundEvent::BreakTough => "break_tough",
+            SoundEvent::BreakCracked => "break_cracked",
+            SoundEvent::BreakExplosive => "break_explosive",
+            SoundEvent::EnemyKilled => "enemy_killed",
+            SoundEvent::BossHit => "boss_hit",
+            SoundEvent::GameOver => "game_over",
+        }
+    }
+
+    /// The ball hitting a brick of this type
+    pub fn brick_hit(brick: Brick) -> Self {
+        match brick {
+            Brick::Empty | Brick::Normal | Brick::Solid => SoundEvent::Break,
+            Brick::Tough => SoundEvent::BreakTough,
+            Brick::Cracked => SoundEvent::BreakCracked,
+            Brick::Explosive => SoundEvent::BreakExplosive,
+        }
+    }
+
+    /// Whether the event gets heard no matter how many sounds are playing
+    pub fn always_plays(self) -> bool {
+        matches!(self, SoundEvent::GameOver)
+    }
+
+    /// What plays for the event when it has no sounds of its own
+    fn fallback(self) -> Option<SoundEvent> {
+        match self {
+            SoundEvent::PaddleStruck | SoundEvent::WarpOpened => Some(SoundEvent::Bounce),
+            SoundEvent::BreakTough | SoundEvent::BreakCracked | SoundEvent::BreakExplosive |
+            SoundEvent::EnemyKilled | SoundEvent::BossHit => Some(SoundEvent::Break),
+            SoundEvent::Bounce | SoundEvent::Break | SoundEvent::GameOver => None,
+        }
+    }
+
+    /// The effect built into the game, for when the manifest or its files are missing
+    fn preset(self) -> Option<(SfxrParams, SoundEntry)> {
+        let entry = |volume, priority, detune, combo_pitch| SoundEntry {
+            sounds: Vec::new(),
+            volume,
+            priority,
+            cooldown: 0.0,
+            detune,
+            combo_pitch,
+        };
+
+        match self {
+            SoundEvent::Bounce => Some((SfxrParams::ball(), entry(0.23, 0, 0.2, false))),
+            SoundEvent::Break => Some((SfxrParams::brick_break(), entry(0.4, 1, 0.2, true))),
+            SoundEvent::GameOver => Some((SfxrParams::dead(), entry(0.4, 3, 0.0, false))),
+            _ => None,
+        }
+    }
+}
+
+/// What the manifest says about an event
+#[derive(Clone, Debug, DeJson)]
+pub struct SoundEntry {
+    /// Paths relative to `assets`, either `.sfxr` files or 16 bit PCM `.wav` files.
+    /// A random one of them plays every time.
+    pub sounds: Vec<String>,
+    #[nserde(default = "1.0")]
+    pub volume: f32,
+    /// Decides which sounds give way when too many play at once
+    #[nserde(default)]
+    pub priority: u32,
+    /// Seconds before the event can be heard again
+    #[nserde(default)]
+    pub cooldown: f32,
+    /// How far the pitch gets shifted at random, in semitones either way
+    #[nserde(default)]
+    pub detune: f32,
+    /// Whether the pitch climbs as the ball keeps breaking bricks
+    #[nserde(default)]
+    pub combo_pitch: bool,
+}
+
+impl SoundEntry {
+    /// Every pitch, in semitones, the sounds of the entry can be played at
+    fn semitones(&self) -> Vec<f32> {
+        let detune: &[f32] = if self.detune > 0.0 {
+            &[-self.detune, 0.0, self.detune]
+        } else {
+            &[0.0]
+        };
+        let combo: &[f32] = if self.combo_pitch { &COMBO_SEMITONES } else { &[0.0] };
+
+        combo.iter().flat_map(|x| detune.iter().map(move |y| x + y)).collect()
+    }
+
+    /// A random shift within the detune range. It lands on one of [Self::semitones].
+    pub fn random_detune(&self) -> f32 {
+        self.detune * rand::gen_range(-1, 2) as f32
+    }
+}
+
+/// An entry along with its sounds, ready to play
+pub struct BankEntry {
+    /// Tells apart the sounds of different entries
+    pub id: usize,
+    pub entry: SoundEntry,
+    pub variants: Vec<VariedSound>,
+}
+
+/// The sounds of every event. Whatever fails to load gets replaced by
+/// something built in, so a broken manifest never stops the game.
+pub struct SoundBank {
+    /// In the order of [SoundEvent::ALL]
+    entries: Vec<Option<BankEntry>>,
+}
+
+impl SoundBank {
+    pub async fn load() -> Self {
+        let mut manifest = match load_string(MANIFEST_PATH).await {
+            Ok(src) => HashMap::<String, SoundEntry>::deserialize_json(&src).unwrap_or_else(|e| {
+                warn!("Using the built-in sounds, {MANIFEST_PATH} is broken: {e}");
+                HashMap::new()
+            }),
+            Err(e) => {
+                warn!("Using the built-in sounds: {e}");
+                HashMap::new()
+            },
+        };
+
+        let mut entries = Vec::with_capacity(SoundEvent::ALL.len());
+        for event in SoundEvent::ALL {
+            let id = entries.len();
+            let loaded = match manifest.remove(event.name()) {
+                Some(entry) => load_entry(id, entry).await,
+                None => None,
+            };
+            let loaded = match (loaded, event.preset()) {
+                (Some(x), _) => Some(x),
+                (None, Some((params, entry))) => render(id, entry, &[params.synth(SFX_SEED)]).await,
+                (None, None) => None,
+            };
+
+            entries.push(loaded);
+        }
+
+        for name in manifest.keys() {
+            warn!("{MANIFEST_PATH} has an unknown event {name:?}");
+        }
+
+        Self { entries }
+    }
+
+    /// The sounds of the event, or of whatever it falls back to
+    pub fn get(&self, event: SoundEvent) -> Option<&BankEntry> {
+        self.entries[event as usize].as_ref().or_else(|| self.get(event.fallback()?))
+    }
+}
+
+/// Loads every sound the entry lists, skipping the ones that fail.
+/// `None` if none of them worked out.
+async fn load_entry(id: usize, entry: SoundEntry) -> Option<BankEntry> {
+    let mut samples = Vec::new();
+    for path in &entry.sounds {
+        match load_samples(path).await {
+            Ok(x) => samples.push(x),
+            Err(e) => warn!("Skipping the sound {path}: {e}"),
+        }
+    }
+
+    render(id, entry, &samples).await
+}
+
+async fn load_samples(path: &str) -> anyhow::Result<Samples> {
+    let bytes = load_file(&format!("assets/{path}")).await?;
+
+    if path.ends_with(".sfxr") {
+        Ok(SfxrParams::from_sfxr_file(&bytes)?.synth(SFX_SEED))
+    } else {
+        Samples::from_wav(&bytes)
+    }
+}
+
+/// Renders the samples at every pan and pitch the entry needs
+async fn render(id: usize, entry: SoundEntry, samples: &[Samples]) -> Option<BankEntry> {
+    let pitches = entry.semitones().into_iter()
+        .map(semitone_pitch)
+        .collect::<Vec<_>>();
+
+    let mut variants = Vec::with_capacity(samples.len());
+    for x in samples {
+        match VariedSound::new(x, &pitches).await {
+            Ok(x) => variants.push(x),
+            Err(e) => warn!("Failed to render a sound: {e}"),
+        }
+    }
+
+    if variants.is_empty() {
+        return None;
+    }
+
+    Some(BankEntry { id, entry, variants })
+}
+
+pub fn semitone_pitch(semitones: f32) -> f32 {
+    2.0f32.powf(semitones / 12.0)
+}
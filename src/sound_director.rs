@@ -1,49 +1,285 @@
-use macroquad::audio::{self, load_sound, PlaySoundParams, Sound};
+use macroquad::{audio::{self, load_sound, PlaySoundParams, Sound}, prelude::*};
 
-use crate::game_model::GameModel;
+use crate::{
+    boss::BOSS_WIDTH,
+    game_model::GameModel,
+    mixer::Voices,
+    physics::{Physics, MAX_X},
+    settings::Settings,
+    sound_bank::{semitone_pitch, SoundBank, SoundEvent, COMBO_SEMITONES},
+    GameState,
+};
+
+/// How many effects can play at once before they start cutting each other off
+const MAX_VOICES: usize = 8;
+
+/// How long it takes one track to fade into another
+const CROSSFADE_TIME: f32 = 1.2;
+/// Long frames (like the first one after the window comes back) don't skip the fade
+const MAX_FADE_DT: f32 = 1.0 / 30.0;
+/// How loud the music stays while the game is paused
+const PAUSE_DUCK: f32 = 0.35;
+const MUSIC_VOLUME: f32 = 0.5;
+
+/// Groups of sounds that share a volume level. Everything also goes through the master level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bus {
+    Sfx,
+    Music,
+}
+
+/// The music loops. Only one of them plays at a time, apart from crossfades.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Track {
+    Menu,
+    Gameplay,
+    Boss,
+    Victory,
+}
+
+impl Track {
+    pub const ALL: [Track; 4] = [
+        Track::Menu,
+        Track::Gameplay,
+        Track::Boss,
+        Track::Victory,
+    ];
+
+    fn path(self) -> &'static str {
+        match self {
+            Track::Menu => "assets/music_menu.wav",
+            Track::Gameplay => "assets/music_game.wav",
+            Track::Boss => "assets/music_boss.wav",
+            Track::Victory => "assets/music_victory.wav",
+        }
+    }
+
+    /// What should be playing, `None` for silence. The rotation prompt
+    /// keeps whatever was on before it.
+    fn pick(model: &GameModel, current: Option<Track>) -> Option<Track> {
+        match model.state {
+            GameState::Start | GameState::Settings | GameState::Editor => Some(Track::Menu),
+            GameState::Active | GameState::Paused if model.physics.boss.is_some() => Some(Track::Boss),
+            GameState::Active | GameState::Paused => Some(Track::Gameplay),
+            GameState::Win => Some(Track::Victory),
+            GameState::GameOver => None,
+            GameState::PleaseRotate => current,
+        }
+    }
+}
+
+/// A music loop along with how far it has faded in
+struct MusicTrack {
+    /// `None` if the file failed to load, the track stays silent then
+    sound: Option<Sound>,
+    fade: f32,
+    playing: bool,
+}
 
 pub struct SoundDirector {
-    dead: Sound,
-    bsound: Sound,
-    bounce: Sound,
+    bank: SoundBank,
+    voices: Voices,
+    /// When each event was last heard, in the order of [SoundEvent::ALL]
+    last_played: [f64; SoundEvent::ALL.len()],
+    music: [MusicTrack; 4],
+    current: Option<Track>,
+    /// Follows the music bus level, so that the music comes back gently
+    music_gain: f32,
+    master: f32,
+    sfx: f32,
+    music_level: f32,
+    muted: bool,
+    focused: bool,
 }
 
 impl SoundDirector {
-    pub async fn new() -> anyhow::Result<Self> {
-        Ok(Self {
-            dead: load_sound("assets/dead.wav").await?,
-            bsound: load_sound("assets/break.wav").await?,
-            bounce: load_sound("assets/ball.wav").await?,
-        })
+    /// Anything that fails to load only gets a warning, the game can do without it
+    pub async fn new() -> Self {
+        Self {
+            bank: SoundBank::load().await,
+            voices: Voices::new(MAX_VOICES),
+            last_played: [f64::NEG_INFINITY; SoundEvent::ALL.len()],
+            music: [
+                Self::load_track(Track::Menu).await,
+                Self::load_track(Track::Gameplay).await,
+                Self::load_track(Track::Boss).await,
+                Self::load_track(Track::Victory).await,
+            ],
+            current: None,
+            music_gain: 0.0,
+            master: 1.0,
+            sfx: 1.0,
+            music_level: 1.0,
+            muted: false,
+            focused: true,
+        }
     }
 
-    pub fn direct_sounds(&mut self, model: &GameModel) {
-        if model.ball_bounced() && model.broken_box().is_none() {
-            audio::play_sound(
-                &self.bounce,
-                PlaySoundParams {
-                    looped: false,
-                    volume: 0.23,
-                }
-            );
-        } else if model.ball_bounced() {
-            audio::play_sound(
-                &self.bsound,
-                PlaySoundParams {
-                    looped: false,
-                    volume: 0.4,
-                }
-            );
+    async fn load_track(track: Track) -> MusicTrack {
+        let sound = match load_sound(track.path()).await {
+            Ok(x) => Some(x),
+            Err(e) => {
+                warn!("No {track:?} music: {e}");
+                None
+            },
+        };
+
+        MusicTrack {
+            sound,
+            fade: 0.0,
+            playing: false,
+        }
+    }
+
+    /// Picks up the bus levels and the mute switch
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.master = settings.master_volume;
+        self.sfx = settings.sfx_volume;
+        self.music_level = settings.music_volume;
+        self.muted = settings.muted;
+    }
+
+    /// Everything stays quiet while the window is in the background
+    pub fn set_focused(&mut self, focused: bool) {
+        self.focused = focused;
+    }
+
+    /// What a sound played on the bus gets its volume multiplied by
+    pub fn bus_volume(&self, bus: Bus) -> f32 {
+        if self.muted || !self.focused {
+            return 0.0;
+        }
+
+        self.master * match bus {
+            Bus::Sfx => self.sfx,
+            Bus::Music => self.music_level,
         }
+    }
+
+    /// Plays a random variant of the event, panned to where it happened in the arena.
+    /// Sounds still cooling down, or without a free voice, get skipped.
+    fn play(&mut self, event: SoundEvent, x: f32, combo: u32) {
+        let Some(sound) = self.bank.get(event) else {
+            return;
+        };
+        let entry = &sound.entry;
+        let now = get_time();
+        let forced = event.always_plays();
+        if !forced && now - self.last_played[event as usize] < entry.cooldown as f64 {
+            return;
+        }
+
+        let volume = entry.volume * self.bus_volume(Bus::Sfx);
+        if volume <= 0.0 {
+            return;
+        }
+
+        let combo = if entry.combo_pitch {
+            COMBO_SEMITONES[(combo as usize).saturating_sub(1).min(COMBO_SEMITONES.len() - 1)]
+        } else {
+            0.0
+        };
+        let variant_idx = rand::gen_range(0, sound.variants.len());
+        let variant = &sound.variants[variant_idx];
+        let pan = (x / MAX_X * 2.0 - 1.0).clamp(-1.0, 1.0);
+        let idx = variant.pick(pan, semitone_pitch(combo + entry.random_detune()));
+
+        let key = (sound.id, variant_idx, idx);
+        if self.voices.play(variant, key, entry.priority, volume, forced, now) {
+            self.last_played[event as usize] = now;
+        }
+    }
+
+    /// Plays what happened in the frame, the most important sounds first
+    pub fn direct_sounds(&mut self, model: &GameModel) {
+        let phys = &model.physics;
+        let mut events = Vec::new();
 
         if model.gameover_just_happened() {
-            audio::play_sound(
-                &self.dead,
-                PlaySoundParams {
-                    looped: false,
-                    volume: 0.4,
-                }
-            );
+            events.push((SoundEvent::GameOver, phys.ball_pos.x));
+        }
+        if model.paddle_struck() {
+            events.push((SoundEvent::PaddleStruck, phys.player_rect().center().x));
+        }
+        if model.warp_opened() {
+            events.push((SoundEvent::WarpOpened, MAX_X));
+        }
+        if let Some((bx, by)) = model.broken_box() {
+            let brick = model.old_physics.boxes[by][bx];
+            events.push((SoundEvent::brick_hit(brick), Physics::box_rect(bx, by).center().x));
+        }
+        events.extend(model.killed_enemies().map(|pos| (SoundEvent::EnemyKilled, pos.x)));
+        if let Some(boss) = phys.boss.filter(|_| model.boss_hit()) {
+            events.push((SoundEvent::BossHit, boss.x + BOSS_WIDTH / 2.0));
+        }
+        // Breaking something drowns out the bounce that came with it
+        if model.ball_bounced() && events.is_empty() {
+            events.push((SoundEvent::Bounce, phys.ball_pos.x));
+        }
+
+        // One of each is enough, several enemies dying at once still sound like one
+        let mut played = Vec::with_capacity(events.len());
+        events.sort_by_key(|(event, _)| {
+            std::cmp::Reverse(self.bank.get(*event).map_or(0, |x| x.entry.priority))
+        });
+        for (event, x) in events {
+            if !played.contains(&event) {
+                self.play(event, x, phys.combo);
+                played.push(event);
+            }
+        }
+    }
+
+    /// Crossfades to the track of the current state. Has to be called every frame.
+    pub fn direct_music(&mut self, model: &GameModel) {
+        let step = model.dt.min(MAX_FADE_DT) / CROSSFADE_TIME;
+        let bus = self.bus_volume(Bus::Music);
+        // Muting and losing focus silence the music at once, only the way back is gradual
+        self.music_gain = if bus <= 0.0 {
+            0.0
+        } else {
+            approach(self.music_gain, bus, step)
+        };
+
+        self.current = Track::pick(model, self.current);
+        let duck = match model.state {
+            GameState::Paused | GameState::PleaseRotate => PAUSE_DUCK,
+            _ => 1.0,
+        };
+
+        for (track, music) in Track::ALL.into_iter().zip(&mut self.music) {
+            let target = if self.current == Some(track) { duck } else { 0.0 };
+            music.fade = approach(music.fade, target, step);
+            let volume = music.fade * self.music_gain * MUSIC_VOLUME;
+            let Some(sound) = &music.sound else {
+                continue;
+            };
+
+            if music.playing && music.fade <= 0.0 {
+                audio::stop_sound(sound);
+                music.playing = false;
+            } else if music.playing {
+                audio::set_sound_volume(sound, volume);
+            } else if music.fade > 0.0 {
+                // Tracks that faded out start over the next time
+                audio::play_sound(
+                    sound,
+                    PlaySoundParams {
+                        looped: true,
+                        volume,
+                    }
+                );
+                music.playing = true;
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Moves `value` towards `target` by at most `step`
+fn approach(value: f32, target: f32, step: f32) -> f32 {
+    if value < target {
+        (value + step).min(target)
+    } else {
+        (value - step).max(target)
+    }
+}
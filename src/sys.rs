@@ -15,6 +15,11 @@ mod platform {
             pub fn app_is_on_mobile() -> bool;
             pub fn app_get_orientation() -> f32;
             pub fn panic_screen(msg: JsObject);
+            pub fn app_gamepad_buttons() -> u32;
+            pub fn app_has_focus() -> bool;
+            pub fn app_storage_load(key: JsObject) -> JsObject;
+            pub fn app_storage_store(key: JsObject, data: JsObject) -> bool;
+            pub fn app_storage_remove(key: JsObject) -> bool;
         }
     }
 
@@ -33,6 +38,50 @@ mod platform {
     pub fn panic_screen(msg: &str) {
         unsafe { imports::panic_screen(JsObject::string(msg)); }
     }
+
+    pub fn gamepad_buttons() -> u32 {
+        unsafe { imports::app_gamepad_buttons() }
+    }
+
+    pub fn has_focus() -> bool {
+        unsafe { imports::app_has_focus() }
+    }
+
+    /// Other pages on the same origin share localStorage
+    fn storage_key(key: &str) -> JsObject {
+        JsObject::string(&format!("quad-arcanoid/{key}"))
+    }
+
+    pub fn storage_load(key: &str) -> Option<String> {
+        let res = unsafe { imports::app_storage_load(storage_key(key)) };
+        if res.is_nil() {
+            return None;
+        }
+
+        let mut data = String::new();
+        res.to_string(&mut data);
+
+        Some(data)
+    }
+
+    pub fn storage_store(key: &str, data: &str) -> anyhow::Result<()> {
+        let stored = unsafe {
+            imports::app_storage_store(storage_key(key), JsObject::string(data))
+        };
+        if !stored {
+            anyhow::bail!("localStorage refused {key}");
+        }
+
+        Ok(())
+    }
+
+    pub fn storage_remove(key: &str) -> anyhow::Result<()> {
+        if !unsafe { imports::app_storage_remove(storage_key(key)) } {
+            anyhow::bail!("localStorage refused to remove {key}");
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(not(target_family = "wasm"))]
@@ -46,6 +95,46 @@ mod platform {
     }
 
     pub fn panic_screen(_msg: &str) { }
+
+    /// Macroquad keeps the focus events to itself, so the native
+    /// window can't tell when it is in the background
+    pub fn has_focus() -> bool { true }
+
+    pub fn storage_load(key: &str) -> Option<String> {
+        std::fs::read_to_string(storage_path(key)).ok()
+    }
+
+    pub fn storage_store(key: &str, data: &str) -> anyhow::Result<()> {
+        let path = storage_path(key);
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, data)?;
+
+        Ok(())
+    }
+
+    pub fn storage_remove(key: &str) -> anyhow::Result<()> {
+        match std::fs::remove_file(storage_path(key)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Everything persistent lives in the user's config dir
+    fn storage_path(key: &str) -> std::path::PathBuf {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .map(std::path::PathBuf::from)
+            .or_else(|| std::env::var_os("APPDATA").map(std::path::PathBuf::from))
+            .or_else(|| {
+                std::env::var_os("HOME")
+                    .map(|x| std::path::PathBuf::from(x).join(".config"))
+            })
+            .unwrap_or_default();
+
+        base.join("quad-arcanoid").join(format!("{key}.json"))
+    }
 }
 
 pub use platform::*;
\ No newline at end of file
@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context};
+use macroquad::prelude::*;
+use macroquad_particles::EmitterConfig;
+use nanoserde::{DeJson, SerJson};
+
+use crate::{
+    particle_fx::{self, Effect, ParticleStyle},
+    physics::Brick,
+};
+
+pub const THEME_DIR: &str = "assets/themes";
+pub const THEME_INDEX: &str = "index.txt";
+/// The theme made of the original assets. A theme file with this name
+/// changes it, every other theme starts from it.
+pub const DEFAULT_THEME: &str = "classic";
+
+/// The colours everything in the arena gets drawn with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    /// Around the arena
+    pub background: Color,
+    pub arena: Color,
+    pub light: Color,
+    pub dark: Color,
+    pub cool: Color,
+    pub warm: Color,
+    pub hot: Color,
+    pub cracked: Color,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            background: Color::new(0.0, 0.0, 0.02, 1.0),
+            arena: Color::new(0.0, 0.0, 0.12, 1.0),
+            light: Color::from_hex(0xDDFBFF),
+            dark: Color::from_hex(0x333354),
+            cool: Color::from_hex(0x7FD2FF),
+            warm: Color::from_hex(0xFFD27F),
+            hot: Color::from_hex(0xFF7F9F),
+            cracked: Color::from_hex(0x4C7E99),
+        }
+    }
+}
+
+impl Palette {
+    /// The colour a brick type gets multiplied with
+    pub fn brick_tint(&self, brick: Brick) -> Color {
+        match brick {
+            Brick::Empty => BLANK,
+            Brick::Normal => WHITE,
+            Brick::Tough => self.cool,
+            Brick::Cracked => self.cracked,
+            Brick::Solid => self.warm,
+            Brick::Explosive => self.hot,
+        }
+    }
+
+    /// The palette with the brick and enemy colours swapped for ones the
+    /// vision tells apart. High contrast puts everything on black.
+    pub fn adapted(mut self, vision: ColorVision, high_contrast: bool) -> Self {
+        if let Some([cool, warm, hot, cracked]) = vision.accents() {
+            self.cool = Color::from_hex(cool);
+            self.warm = Color::from_hex(warm);
+            self.hot = Color::from_hex(hot);
+            self.cracked = Color::from_hex(cracked);
+        }
+
+        if high_contrast {
+            self.background = BLACK;
+            self.arena = BLACK;
+            self.light = WHITE;
+            self.dark = Color::from_hex(0x505050);
+        }
+
+        self
+    }
+
+    fn color_mut(&mut self, name: &str) -> Option<&mut Color> {
+        match name {
+            "background" => Some(&mut self.background),
+            "arena" => Some(&mut self.arena),
+            "light" => Some(&mut self.light),
+            "dark" => Some(&mut self.dark),
+            "cool" => Some(&mut self.cool),
+            "warm" => Some(&mut self.warm),
+            "hot" => Some(&mut self.hot),
+            "cracked" => Some(&mut self.cracked),
+            _ => None,
+        }
+    }
+}
+
+/// Colour vision deficiencies the palette can make up for
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, DeJson, SerJson)]
+pub enum ColorVision {
+    #[default]
+    Normal,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+}
+
+impl ColorVision {
+    pub const ALL: [ColorVision; 4] = [
+        ColorVision::Normal,
+        ColorVision::Deuteranopia,
+        ColorVision::Protanopia,
+        ColorVision::Tritanopia,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColorVision::Normal => "normal",
+            ColorVision::Deuteranopia => "deuteranopia",
+            ColorVision::Protanopia => "protanopia",
+            ColorVision::Tritanopia => "tritanopia",
+        }
+    }
+
+    /// Moves a number of entries along [ColorVision::ALL], going around at the ends
+    pub fn step(self, steps: i32) -> Self {
+        let idx = (self as i32 + steps).rem_euclid(Self::ALL.len() as i32);
+        Self::ALL[idx as usize]
+    }
+
+    /// The cool, warm, hot and cracked colours. They differ in brightness
+    /// as well, so they stay apart even when their hues look alike.
+    fn accents(self) -> Option<[u32; 4]> {
+        match self {
+            ColorVision::Normal => None,
+            ColorVision::Deuteranopia => Some([0x56B4E9, 0xF0E442, 0xD55E00, 0x0072B2]),
+            ColorVision::Protanopia => Some([0x0072B2, 0xF0E442, 0xE69F00, 0x56B4E9]),
+            ColorVision::Tritanopia => Some([0x00B3B3, 0xFF9DA7, 0xD60000, 0x5C7A7A]),
+        }
+    }
+}
+
+/// A theme as it is stored in `assets/themes/<id>.json`. Whatever is left
+/// out comes from the default theme. Texture paths are relative to `assets`.
+#[derive(Clone, Debug, DeJson)]
+pub struct ThemeFile {
+    /// Colours by their [Palette] field name, as `#RRGGBB` or `#RRGGBBAA`
+    #[nserde(default)]
+    pub palette: HashMap<String, String>,
+    /// `ball` and `paddle` take several frames, the rest only use the first one.
+    /// `background` is drawn over the arena.
+    #[nserde(default)]
+    pub textures: HashMap<String, Vec<String>>,
+    /// Frames per second of the `ball`, `paddle`, `bricks` and `boss` animations
+    #[nserde(default)]
+    pub frame_rates: HashMap<String, f32>,
+    #[nserde(default)]
+    pub particles: HashMap<String, ParticleStyle>,
+}
+
+/// How everything in the arena looks
+#[derive(Clone)]
+pub struct Theme {
+    /// The file name without the extension, which levels and the settings refer to
+    pub id: String,
+    pub palette: Palette,
+    pub ball: Vec<Texture2D>,
+    pub paddle: Vec<Texture2D>,
+    pub bricks: Texture2D,
+    pub outline: Texture2D,
+    pub wall: Texture2D,
+    pub particle: Texture2D,
+    pub background: Option<Texture2D>,
+    pub ball_fps: f32,
+    pub paddle_fps: f32,
+    pub brick_fps: f32,
+    pub boss_fps: f32,
+    /// In the order of [Effect::ALL]
+    particles: Vec<EmitterConfig>,
+}
+
+impl Theme {
+    /// The original look of the game
+    async fn builtin(textures: &mut TextureCache, particles: Vec<EmitterConfig>) -> anyhow::Result<Self> {
+        Ok(Self {
+            id: DEFAULT_THEME.to_owned(),
+            palette: Palette::default(),
+            ball: vec![
+                textures.load("ball1.png").await?,
+                textures.load("ball2.png").await?,
+                textures.load("ball3.png").await?,
+            ],
+            paddle: vec![
+                textures.load("pl1.png").await?,
+                textures.load("pl2.png").await?,
+                textures.load("pl3.png").await?,
+            ],
+            bricks: textures.load("bricks.png").await?,
+            outline: textures.load("brick_outline.png").await?,
+            wall: textures.load("wall.png").await?,
+            particle: textures.load("ded.png").await?,
+            background: None,
+            ball_fps: 5.0,
+            paddle_fps: 5.0,
+            brick_fps: 1.0,
+            boss_fps: 2.0,
+            particles,
+        })
+    }
+
+    /// The theme the file describes on top of `base`. Broken parts of the
+    /// file only get a warning and keep what `base` has.
+    async fn from_file(id: &str, file: &ThemeFile, base: &Theme, textures: &mut TextureCache) -> Self {
+        let mut res = Self {
+            id: id.to_owned(),
+            ..base.clone()
+        };
+
+        for (key, value) in &file.palette {
+            let Some(color) = res.palette.color_mut(key) else {
+                warn!("Theme {id} has an unknown colour {key:?}");
+                continue;
+            };
+            match parse_color(value) {
+                Ok(x) => *color = x,
+                Err(e) => warn!("Theme {id}, colour {key}: {e}"),
+            }
+        }
+
+        for (key, paths) in &file.textures {
+            let mut frames = Vec::with_capacity(paths.len());
+            for path in paths {
+                match textures.load(path).await {
+                    Ok(x) => frames.push(x),
+                    Err(e) => warn!("Theme {id}, texture {path}: {e}"),
+                }
+            }
+            let Some(first) = frames.first().cloned() else {
+                continue;
+            };
+
+            match key.as_str() {
+                "ball" => res.ball = frames,
+                "paddle" => res.paddle = frames,
+                "bricks" => res.bricks = first,
+                "outline" => res.outline = first,
+                "wall" => res.wall = first,
+                "particle" => res.particle = first,
+                "background" => res.background = Some(first),
+                _ => warn!("Theme {id} has an unknown texture {key:?}"),
+            }
+        }
+
+        for (key, fps) in &file.frame_rates {
+            match key.as_str() {
+                "ball" => res.ball_fps = *fps,
+                "paddle" => res.paddle_fps = *fps,
+                "bricks" => res.brick_fps = *fps,
+                "boss" => res.boss_fps = *fps,
+                _ => warn!("Theme {id} has an unknown animation {key:?}"),
+            }
+        }
+
+        particle_fx::apply_styles(&mut res.particles, &file.particles, &format!("Theme {id}"));
+
+        res
+    }
+
+    /// The emitter settings of the effect, at the full particle amount
+    pub fn emitter(&self, effect: Effect) -> EmitterConfig {
+        EmitterConfig {
+            texture: effect.textured().then(|| self.particle.clone()),
+            ..self.particles[effect as usize].clone()
+        }
+    }
+}
+
+/// The frame of an animation that is showing right now
+pub fn frame(frames: &[Texture2D], fps: f32) -> &Texture2D {
+    &frames[(get_time() as f32 * fps) as usize % frames.len()]
+}
+
+/// Themes share the textures they have in common
+struct TextureCache(HashMap<String, Texture2D>);
+
+impl TextureCache {
+    async fn load(&mut self, path: &str) -> anyhow::Result<Texture2D> {
+        if let Some(x) = self.0.get(path) {
+            return Ok(x.clone());
+        }
+
+        let texture = load_texture(&format!("assets/{path}")).await?;
+        self.0.insert(path.to_owned(), texture.clone());
+
+        Ok(texture)
+    }
+}
+
+/// Loads every theme the index lists, with the default one first.
+/// Only the textures of the default theme are a must.
+pub async fn load_themes() -> anyhow::Result<Vec<Theme>> {
+    let mut textures = TextureCache(HashMap::new());
+    let mut res = vec![Theme::builtin(&mut textures, particle_fx::load_library().await).await?];
+
+    let index = match load_string(&format!("{THEME_DIR}/{THEME_INDEX}")).await {
+        Ok(x) => x,
+        Err(e) => {
+            warn!("Only the default theme is there: {e}");
+            return Ok(res);
+        },
+    };
+
+    for id in index.lines().map(str::trim).filter(|x| !x.is_empty()) {
+        let file = match load_theme_file(id).await {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("Skipping the theme {id}: {e}");
+                continue;
+            },
+        };
+        let theme = Theme::from_file(id, &file, &res[0], &mut textures).await;
+
+        match res.iter_mut().find(|x| x.id == id) {
+            Some(x) => *x = theme,
+            None => res.push(theme),
+        }
+    }
+
+    Ok(res)
+}
+
+async fn load_theme_file(id: &str) -> anyhow::Result<ThemeFile> {
+    let src = load_string(&format!("{THEME_DIR}/{id}.json")).await?;
+
+    Ok(ThemeFile::deserialize_json(&src)?)
+}
+
+/// Reads `#RRGGBB` or `#RRGGBBAA`
+pub fn parse_color(src: &str) -> anyhow::Result<Color> {
+    let hex = src.strip_prefix('#').unwrap_or(src);
+    let value = u32::from_str_radix(hex, 16)
+        .with_context(|| format!("{src:?} is not a colour"))?;
+
+    match hex.len() {
+        6 => Ok(Color::from_hex(value)),
+        8 => Ok(Color::from_rgba(
+            (value >> 24) as u8,
+            (value >> 16) as u8,
+            (value >> 8) as u8,
+            value as u8,
+        )),
+        _ => bail!("{src:?} is not a colour, expected #RRGGBB or #RRGGBBAA"),
+    }
+}
@@ -1,24 +1,82 @@
+use std::ops::Range;
+
 use macroquad::prelude::*;
-use crate::{sys::*, GameState};
+use crate::{
+    editor::Editor,
+    input::{ActionFrame, TouchZones},
+    level::{Level, LevelKind},
+    physics::Brick,
+    render::draw_brick_pattern,
+    sys::*,
+    theme::Palette,
+    GameState,
+};
 
 const FONT_SCALE: f32 = 1.0;
 const MAIN_FONT_SIZE: u16 = 32;
 const HINT_FONT_SIZE: u16 = 16;
 const PADDLE_BUTTON_WIDTH: f32 = 64.0;
 const VERTICAL_ORIENT_HORIZONTAL_PADDING: f32 = 16.0;
+const TOOLBAR_PADDING: f32 = 4.0;
+const TOOLBAR_BUTTON_HEIGHT: f32 = 24.0;
+const TOOLBAR_TOP: f32 = 32.0;
+const MENU_ENTRY_WIDTH: f32 = 320.0;
+const MENU_LINE_HEIGHT: f32 = 24.0;
+const HUD_PADDING: f32 = 8.0;
+
+const BRUSH_KEYS: [KeyCode; 6] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+];
 
 static WIN_TEXT: &'static str = "Congratulations!";
 static GAMEOVER_TEXT: &'static str = "Game Over";
 static PAUSE_TEXT: &'static str = "Paused";
 static ORIENTATION_TEXT: &'static str = "Wrong Orientation";
 
-static RESTART_HINT_DESK: &'static str = "Press Space to restart";
+static RESTART_HINT_DESK: &'static str = "Press Space to restart\nEscape to go back to the menu";
 static RESTART_HINT_MOBILE: &'static str = "Tap the screen to restart";
 static ORIENTATION_HINT: &'static str = "Please re-orient your device\ninto landscape";
 
 static START_TEXT_DESK: &'static str = "Press Space to start";
 static START_TEXT_MOBILE: &'static str = "Tap to start";
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EditorButton {
+    Save,
+    Play,
+    Prev,
+    Next,
+    New,
+    Exit,
+}
+
+impl EditorButton {
+    const ALL: [EditorButton; 6] = [
+        EditorButton::Save,
+        EditorButton::Play,
+        EditorButton::Prev,
+        EditorButton::Next,
+        EditorButton::New,
+        EditorButton::Exit,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            EditorButton::Save => "Save",
+            EditorButton::Play => "Play",
+            EditorButton::Prev => "Prev",
+            EditorButton::Next => "Next",
+            EditorButton::New => "New",
+            EditorButton::Exit => "Exit",
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct InGameUiModel {
     state: GameState,
@@ -27,6 +85,15 @@ pub struct InGameUiModel {
     confirmation_detected: bool,
     pause_requested: bool,
     fullscreen_toggle_requested: bool,
+    editor_requested: bool,
+    assist_toggle_requested: bool,
+    menu_move: i32,
+    menu_clicked: Option<usize>,
+    seed_reroll_requested: bool,
+    seed_paste_requested: bool,
+    seed_copy_requested: bool,
+    mute_toggle_requested: bool,
+    menu_adjust: i32,
 }
 
 impl InGameUiModel {
@@ -49,6 +116,112 @@ impl InGameUiModel {
     pub fn fullscreen_toggle_requested(&self) -> bool {
         self.fullscreen_toggle_requested
     }
+
+    pub fn editor_requested(&self) -> bool {
+        self.editor_requested
+    }
+
+    pub fn assist_toggle_requested(&self) -> bool {
+        self.assist_toggle_requested
+    }
+
+    /// How many entries to move the menu selection by
+    pub fn menu_move(&self) -> i32 {
+        self.menu_move
+    }
+
+    /// The menu entry that got clicked or tapped
+    pub fn menu_clicked(&self) -> Option<usize> {
+        self.menu_clicked
+    }
+
+    pub fn seed_reroll_requested(&self) -> bool {
+        self.seed_reroll_requested
+    }
+
+    pub fn seed_paste_requested(&self) -> bool {
+        self.seed_paste_requested
+    }
+
+    pub fn seed_copy_requested(&self) -> bool {
+        self.seed_copy_requested
+    }
+
+    pub fn mute_toggle_requested(&self) -> bool {
+        self.mute_toggle_requested
+    }
+
+    /// How far to turn the selected setting down or up
+    pub fn menu_adjust(&self) -> i32 {
+        self.menu_adjust
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EditorUiModel {
+    cursor: Vec2,
+    paint: bool,
+    erase: bool,
+    brush: Option<Brick>,
+    speed_change: f32,
+    toggle_kind: bool,
+    toggle_enemies: bool,
+    level_switch: i32,
+    new_level: bool,
+    save: bool,
+    playtest: bool,
+    exit: bool,
+}
+
+impl EditorUiModel {
+    /// Mouse position in screen coordinates
+    pub fn cursor(&self) -> Vec2 {
+        self.cursor
+    }
+
+    pub fn paint(&self) -> bool {
+        self.paint
+    }
+
+    pub fn erase(&self) -> bool {
+        self.erase
+    }
+
+    pub fn brush(&self) -> Option<Brick> {
+        self.brush
+    }
+
+    pub fn speed_change(&self) -> f32 {
+        self.speed_change
+    }
+
+    pub fn toggle_kind(&self) -> bool {
+        self.toggle_kind
+    }
+
+    pub fn toggle_enemies(&self) -> bool {
+        self.toggle_enemies
+    }
+
+    pub fn level_switch(&self) -> i32 {
+        self.level_switch
+    }
+
+    pub fn new_level(&self) -> bool {
+        self.new_level
+    }
+
+    pub fn save(&self) -> bool {
+        self.save
+    }
+
+    pub fn playtest(&self) -> bool {
+        self.playtest
+    }
+
+    pub fn exit(&self) -> bool {
+        self.exit
+    }
 }
 
 pub struct Ui {
@@ -62,43 +235,194 @@ impl Ui {
         })
     }
 
-    pub fn update(&self, state: GameState) -> InGameUiModel {
-        let (mx, my) = mouse_position();
-        let Vec2 { x: mx, y: my } = self.get_cam().screen_to_world(vec2(mx, my));
-        let left_button_rect = self.move_left_button_rect();
-        let right_button_rect = self.move_right_button_rect();
-
-
-        let left_movement_down =
-            is_key_down(KeyCode::A) ||
-            is_key_down(KeyCode::Left) ||
-            (left_button_rect.contains(vec2(mx, my)) &&
-             is_mouse_button_down(MouseButton::Left) &&
-             on_mobile());
-        let right_movement_down =
-            is_key_down(KeyCode::D) ||
-            is_key_down(KeyCode::Right) ||
-            (right_button_rect.contains(vec2(mx, my)) &&
-             is_mouse_button_down(MouseButton::Left) &&
-             on_mobile());
-        let confirmation_detected =
-            is_key_pressed(KeyCode::Space) ||
-            is_mouse_button_pressed(MouseButton::Left);
-        let pause_requested =
-            is_key_pressed(KeyCode::Escape);
-        let fullscreen_toggle_requested =
-            is_key_pressed(KeyCode::F11);
-
+    /// Turns the merged input of the frame into what the ui cares about
+    pub fn update(&self, state: GameState, frame: &ActionFrame) -> InGameUiModel {
         InGameUiModel {
             state,
-            left_movement_down,
-            right_movement_down,
-            confirmation_detected,
-            pause_requested,
-            fullscreen_toggle_requested,
+            left_movement_down: frame.player.left,
+            right_movement_down: frame.player.right,
+            confirmation_detected: frame.confirm,
+            pause_requested: frame.pause,
+            fullscreen_toggle_requested: frame.fullscreen,
+            editor_requested: frame.editor,
+            assist_toggle_requested: frame.assist,
+            menu_move: frame.menu_move,
+            menu_clicked: frame.menu_clicked,
+            seed_reroll_requested: frame.seed_reroll,
+            seed_paste_requested: frame.seed_paste,
+            seed_copy_requested: frame.seed_copy,
+            mute_toggle_requested: frame.mute,
+            menu_adjust: frame.menu_adjust,
+        }
+    }
+
+    /// Where the touch controls are for the current screen size. Menu
+    /// entries that are scrolled out of view can't be touched.
+    pub fn touch_zones(&self, menu_len: usize, selected: usize) -> TouchZones {
+        let shown = self.shown_entries(menu_len, selected);
+
+        TouchZones {
+            cam: self.get_cam(),
+            left: self.move_left_button_rect(),
+            right: self.move_right_button_rect(),
+            menu: (0..menu_len)
+                .map(|idx| if shown.contains(&idx) {
+                    self.menu_entry_rect(idx - shown.start)
+                } else {
+                    Rect::default()
+                })
+                .collect(),
         }
     }
 
+    pub fn draw_menu(&self, entries: &[String], selected: usize) {
+        set_camera(&self.get_cam());
+
+        let shown = self.shown_entries(entries.len(), selected);
+        for (idx, entry) in entries.iter().enumerate().take(shown.end).skip(shown.start) {
+            let rect = self.menu_entry_rect(idx - shown.start);
+
+            if idx == selected {
+                draw_rectangle(rect.x, rect.y, rect.w, rect.h, Color::from_hex(0x333354));
+            }
+            self.draw_centered_hint(entry, rect.center());
+        }
+    }
+
+    pub fn draw_hud(&self, score: u32, subtitle: Option<&str>) {
+        set_camera(&self.get_cam());
+
+        let view_rect = self.view_rect();
+        let mut text = format!("Score {score}");
+        if let Some(subtitle) = subtitle {
+            text += "\n";
+            text += subtitle;
+        }
+
+        draw_multiline_text_ex(
+            &text,
+            view_rect.left() + HUD_PADDING,
+            view_rect.top() + HUD_PADDING + HINT_FONT_SIZE as f32,
+            None,
+            TextParams {
+                font: Some(&self.oegnek),
+                font_size: HINT_FONT_SIZE,
+                color: Color::from_hex(0xDDFBFF),
+                font_scale: FONT_SCALE,
+                ..Default::default()
+            }
+        );
+    }
+
+    pub fn update_editor(&self) -> EditorUiModel {
+        let cursor = Vec2::from(mouse_position());
+        let ui_cursor = self.get_cam().screen_to_world(cursor);
+        let clicked = is_mouse_button_pressed(MouseButton::Left);
+
+        let brush_rects = (0..Brick::ALL.len()).map(|idx| self.brush_rect(idx));
+        let brush = Brick::ALL.iter()
+            .zip(BRUSH_KEYS)
+            .zip(brush_rects)
+            .find(|((_, key), rect)| {
+                is_key_pressed(*key) || (clicked && rect.contains(ui_cursor))
+            })
+            .map(|((brick, _), _)| *brick);
+        let button = EditorButton::ALL.into_iter()
+            .enumerate()
+            .find(|(idx, _)| clicked && self.editor_button_rect(*idx).contains(ui_cursor))
+            .map(|(_, button)| button);
+        let over_toolbar = button.is_some() || brush.is_some() ||
+            ui_cursor.x < self.brush_rect(0).right() + TOOLBAR_PADDING ||
+            ui_cursor.x > self.editor_button_rect(0).left() - TOOLBAR_PADDING;
+        let paint = is_mouse_button_down(MouseButton::Left) && !over_toolbar;
+        let erase = is_mouse_button_down(MouseButton::Right) && !over_toolbar;
+
+        let mut speed_change = 0.0;
+        if is_key_pressed(KeyCode::LeftBracket) {
+            speed_change -= 1.0;
+        }
+        if is_key_pressed(KeyCode::RightBracket) {
+            speed_change += 1.0;
+        }
+
+        let mut level_switch = 0;
+        if is_key_pressed(KeyCode::PageUp) || button == Some(EditorButton::Prev) {
+            level_switch -= 1;
+        }
+        if is_key_pressed(KeyCode::PageDown) || button == Some(EditorButton::Next) {
+            level_switch += 1;
+        }
+
+        EditorUiModel {
+            cursor,
+            paint,
+            erase,
+            brush,
+            speed_change,
+            toggle_kind: is_key_pressed(KeyCode::B),
+            toggle_enemies: is_key_pressed(KeyCode::E),
+            level_switch,
+            new_level: is_key_pressed(KeyCode::N) || button == Some(EditorButton::New),
+            save: is_key_pressed(KeyCode::S) || button == Some(EditorButton::Save),
+            playtest: is_key_pressed(KeyCode::F5) || button == Some(EditorButton::Play),
+            exit: is_key_pressed(KeyCode::Escape) || is_key_pressed(KeyCode::F2) ||
+                button == Some(EditorButton::Exit),
+        }
+    }
+
+    /// The brushes take the colours of the arena, and its brick patterns when they are on
+    pub fn draw_editor(&self, editor: &Editor, levels: &[Level], palette: &Palette, patterns: bool) {
+        set_camera(&self.get_cam());
+
+        for (idx, brick) in Brick::ALL.into_iter().enumerate() {
+            let rect = self.brush_rect(idx);
+
+            let swatch = Rect::new(
+                rect.x + TOOLBAR_PADDING,
+                rect.y + TOOLBAR_PADDING,
+                rect.w - TOOLBAR_PADDING * 2.0,
+                rect.h - TOOLBAR_PADDING * 2.0,
+            );
+
+            draw_rectangle(rect.x, rect.y, rect.w, rect.h, Color::from_hex(0x333354));
+            draw_rectangle(swatch.x, swatch.y, swatch.w, swatch.h, palette.brick_tint(brick));
+            if patterns {
+                draw_brick_pattern(swatch, brick, palette.background);
+            }
+            if brick == editor.brush {
+                draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 2.0, Color::from_hex(0xDDFBFF));
+            }
+        }
+
+        for (idx, button) in EditorButton::ALL.into_iter().enumerate() {
+            let rect = self.editor_button_rect(idx);
+
+            draw_rectangle(rect.x, rect.y, rect.w, rect.h, Color::from_hex(0x333354));
+            self.draw_centered_hint(button.label(), rect.center());
+        }
+
+        let level = &levels[editor.current];
+        let info = format!(
+            "{}/{} {} ({})  speed {} [ ]  {} B  enemies {} E  {}",
+            editor.current + 1,
+            levels.len(),
+            level.name,
+            level.file,
+            level.ball_speed,
+            match level.kind {
+                LevelKind::Bricks => "bricks",
+                LevelKind::Boss => "boss",
+            },
+            if level.enemies { "on" } else { "off" },
+            editor.status,
+        );
+        let view_rect = self.view_rect();
+        self.draw_centered_hint(
+            &info,
+            vec2(view_rect.center().x, view_rect.bottom() - HINT_FONT_SIZE as f32),
+        );
+    }
+
     pub fn draw(&self, model: InGameUiModel) {
         set_camera(&self.get_cam());
 
@@ -187,6 +511,97 @@ impl Ui {
         }
     }
 
+    /// Draws a list of lines in the top right corner
+    pub fn draw_board(&self, lines: &[String]) {
+        set_camera(&self.get_cam());
+
+        let view_rect = self.view_rect();
+        let text = lines.join("\n");
+        let width = lines.iter()
+            .map(|x| measure_text(x, Some(&self.oegnek), HINT_FONT_SIZE, FONT_SCALE).width)
+            .fold(0.0, f32::max);
+
+        draw_multiline_text_ex(
+            &text,
+            view_rect.right() - HUD_PADDING - width,
+            view_rect.top() + HUD_PADDING + HINT_FONT_SIZE as f32,
+            None,
+            TextParams {
+                font: Some(&self.oegnek),
+                font_size: HINT_FONT_SIZE,
+                color: Color::from_hex(0xDDFBFF),
+                font_scale: FONT_SCALE,
+                ..Default::default()
+            }
+        );
+    }
+
+    /// The entries that fit on the screen. Longer menus scroll along with the selection.
+    fn shown_entries(&self, len: usize, selected: usize) -> Range<usize> {
+        let room = self.view_rect().bottom() - self.menu_entry_rect(0).top();
+        let rows = ((room / MENU_LINE_HEIGHT) as usize).max(1);
+        let first = selected.saturating_sub(rows - 1).min(len.saturating_sub(rows));
+
+        first..len.min(first + rows)
+    }
+
+    /// `row` counts from the top of the visible part of the menu
+    fn menu_entry_rect(&self, row: usize) -> Rect {
+        let view_rect = self.view_rect();
+
+        Rect {
+            x: view_rect.center().x - MENU_ENTRY_WIDTH / 2.0,
+            y: view_rect.center().y + (MAIN_FONT_SIZE as f32) * 1.5 +
+                (row as f32) * MENU_LINE_HEIGHT - MENU_LINE_HEIGHT / 2.0,
+            w: MENU_ENTRY_WIDTH,
+            h: MENU_LINE_HEIGHT,
+        }
+    }
+
+    fn brush_rect(&self, idx: usize) -> Rect {
+        let view_rect = self.view_rect();
+
+        Rect {
+            x: view_rect.left() + TOOLBAR_PADDING,
+            y: TOOLBAR_TOP + (idx as f32) * (TOOLBAR_BUTTON_HEIGHT + TOOLBAR_PADDING),
+            w: PADDLE_BUTTON_WIDTH - TOOLBAR_PADDING * 2.0,
+            h: TOOLBAR_BUTTON_HEIGHT,
+        }
+    }
+
+    fn editor_button_rect(&self, idx: usize) -> Rect {
+        let view_rect = self.view_rect();
+
+        Rect {
+            x: view_rect.right() - PADDLE_BUTTON_WIDTH + TOOLBAR_PADDING,
+            y: TOOLBAR_TOP + (idx as f32) * (TOOLBAR_BUTTON_HEIGHT + TOOLBAR_PADDING),
+            w: PADDLE_BUTTON_WIDTH - TOOLBAR_PADDING * 2.0,
+            h: TOOLBAR_BUTTON_HEIGHT,
+        }
+    }
+
+    fn draw_centered_hint(&self, text: &str, pos: Vec2) {
+        let center = get_text_center(
+            text,
+            Some(&self.oegnek),
+            HINT_FONT_SIZE,
+            FONT_SCALE,
+            0.0
+        );
+        draw_text_ex(
+            text,
+            pos.x - center.x,
+            pos.y - center.y,
+            TextParams {
+                font: Some(&self.oegnek),
+                font_size: HINT_FONT_SIZE,
+                color: Color::from_hex(0xDDFBFF),
+                font_scale: FONT_SCALE,
+                ..Default::default()
+            }
+        );
+    }
+
     fn draw_announcement_text(&self, backdrop: bool, text: &str, hint: Option<&str>) {
         let view_rect = self.view_rect();
 
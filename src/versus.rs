@@ -0,0 +1,199 @@
+use crate::{checksum::Checksum, game_model::player_won, input::PaddleInput, physics::Physics};
+
+/// How many bricks a player has to break to send a garbage row over
+pub const GARBAGE_EVERY: u32 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    pub fn label(self) -> &'static str {
+        match self {
+            Side::Left => "Left player",
+            Side::Right => "Right player",
+        }
+    }
+
+    pub fn index(self) -> usize {
+        match self {
+            Side::Left => 0,
+            Side::Right => 1,
+        }
+    }
+}
+
+/// Keeps count of the garbage rows both sides of a match have sent
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Garbage {
+    sent: [u32; 2],
+}
+
+impl Garbage {
+    /// Sends each side the rows the opponent has earned since the last
+    /// call. Rows that don't fit yet stay owed until there is room.
+    pub fn exchange(&mut self, left: &mut Physics, right: &mut Physics) {
+        Self::send(&mut self.sent[0], left.broken, right);
+        Self::send(&mut self.sent[1], right.broken, left);
+    }
+
+    fn send(sent: &mut u32, broken: u32, to: &mut Physics) {
+        while *sent < broken / GARBAGE_EVERY && to.push_garbage_row() {
+            *sent += 1;
+        }
+    }
+}
+
+/// The first one to clear their bricks wins, otherwise the last one
+/// standing does. When both happen in the same frame, the score decides.
+pub fn decide(
+    (left, left_cleared, left_dropped): (&Physics, bool, bool),
+    (right, right_cleared, right_dropped): (&Physics, bool, bool),
+) -> Option<Side> {
+    let by_score = || if left.score >= right.score { Side::Left } else { Side::Right };
+
+    match (left_cleared, right_cleared) {
+        (true, true) => return Some(by_score()),
+        (true, false) => return Some(Side::Left),
+        (false, true) => return Some(Side::Right),
+        (false, false) => (),
+    }
+
+    match (left_dropped, right_dropped) {
+        (true, true) => Some(by_score()),
+        (true, false) => Some(Side::Right),
+        (false, true) => Some(Side::Left),
+        (false, false) => None,
+    }
+}
+
+/// Both fields of a versus match. It is a plain value, so that online
+/// matches can keep a copy around to roll back to.
+#[derive(Clone, Copy, Debug)]
+pub struct Match {
+    pub left: Physics,
+    pub right: Physics,
+    garbage: Garbage,
+}
+
+impl Match {
+    /// Both players start on the same level
+    pub fn new(phys: Physics) -> Self {
+        Self {
+            left: phys,
+            right: phys,
+            garbage: Garbage::default(),
+        }
+    }
+
+    pub fn field(&self, side: Side) -> &Physics {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+        }
+    }
+
+    /// Simulates a frame, returning the winner once there is one
+    pub fn step(&mut self, [left, right]: [PaddleInput; 2], dt: f32) -> Option<Side> {
+        self.left.new_frame();
+        self.right.new_frame();
+        left.apply(&mut self.left, dt);
+        right.apply(&mut self.right, dt);
+
+        let left_dropped = self.left.update(dt);
+        let right_dropped = self.right.update(dt);
+        self.garbage.exchange(&mut self.left, &mut self.right);
+
+        let left_cleared = player_won(&self.left) || self.left.warped;
+        let right_cleared = player_won(&self.right) || self.right.warped;
+        decide(
+            (&self.left, left_cleared, left_dropped),
+            (&self.right, right_cleared, right_dropped),
+        )
+    }
+
+    /// A hash of the whole state, for telling whether two machines still agree
+    pub fn checksum(&self) -> u64 {
+        let mut sum = Checksum::default();
+        self.left.checksum(&mut sum);
+        self.right.checksum(&mut sum);
+        for sent in self.garbage.sent {
+            sum.u32(sent);
+        }
+
+        sum.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        level::Level,
+        physics::{Brick, BOX_LINE_COUNT, BOX_PER_LINE},
+    };
+
+    fn field() -> Physics {
+        Physics::from_level(&Level::empty("test.lvl".to_owned()), 7)
+    }
+
+    fn garbage_rows(phys: &Physics) -> usize {
+        phys.boxes.iter().filter(|row| row.contains(&Brick::Normal)).count()
+    }
+
+    #[test]
+    fn garbage_waits_for_room() {
+        let (mut left, mut right) = (field(), field());
+        let mut garbage = Garbage::default();
+
+        left.broken = 2 * GARBAGE_EVERY;
+        garbage.exchange(&mut left, &mut right);
+        assert_eq!(garbage_rows(&right), 2);
+
+        // Nothing moves while the bottom row is taken
+        right.boxes[BOX_LINE_COUNT - 1] = [Brick::Solid; BOX_PER_LINE];
+        left.broken = 3 * GARBAGE_EVERY;
+        garbage.exchange(&mut left, &mut right);
+        assert_eq!(garbage_rows(&right), 2);
+
+        right.boxes[BOX_LINE_COUNT - 1] = [Brick::Empty; BOX_PER_LINE];
+        garbage.exchange(&mut left, &mut right);
+        assert_eq!(garbage_rows(&right), 3);
+    }
+
+    #[test]
+    fn garbage_waits_for_the_ball() {
+        let (mut left, mut right) = (field(), field());
+        let mut garbage = Garbage::default();
+
+        right.ball_pos.y = 0.0;
+        left.broken = GARBAGE_EVERY;
+        garbage.exchange(&mut left, &mut right);
+        assert_eq!(garbage_rows(&right), 0);
+
+        right.respawn_ball();
+        garbage.exchange(&mut left, &mut right);
+        assert_eq!(garbage_rows(&right), 1);
+    }
+
+    #[test]
+    fn checksum_sees_every_bit() {
+        let duel = Match::new(field());
+        assert_eq!(duel.checksum(), Match::new(field()).checksum());
+
+        let mut nudged = duel;
+        nudged.right.ball_pos.x = f32::from_bits(duel.right.ball_pos.x.to_bits() + 1);
+        assert_ne!(duel.checksum(), nudged.checksum());
+
+        let mut bricked = duel;
+        bricked.left.boxes[0][0] = Brick::Solid;
+        assert_ne!(duel.checksum(), bricked.checksum());
+
+        let mut rolled = duel;
+        rolled.left.push_garbage_row();
+        rolled.left.boxes = duel.left.boxes;
+        assert_ne!(duel.checksum(), rolled.checksum());
+    }
+}